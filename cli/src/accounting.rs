@@ -0,0 +1,178 @@
+//! Lamport and capitalization accounting.
+//!
+//! A migration burns the lamports of the builtin program account and the
+//! buffer account, and funds the new program and program data accounts with
+//! exactly their rent-exempt minimums. Any other change to the total supply
+//! across the migration, besides inflation rewards, is lamport creation or
+//! loss.
+
+use {
+    crate::{
+        program::Program,
+        report::ensure,
+        validator::{report_checks, ValidatorContext},
+    },
+    solana_rpc_client_api::config::RpcBlockConfig,
+    solana_sdk::{
+        bpf_loader_upgradeable::get_program_data_address, clock::Slot,
+        commitment_config::CommitmentConfig, pubkey::Pubkey, reward_type::RewardType,
+    },
+    solana_transaction_status::TransactionDetails,
+};
+
+/// Balances recorded before the feature activations take effect.
+pub struct AccountingSnapshot {
+    slot: Slot,
+    supply: u64,
+    /// The program account and buffer account lamports, per program.
+    balances: Vec<(String, Pubkey, u64, u64)>,
+}
+
+impl ValidatorContext {
+    async fn get_lamports(&self, address: &Pubkey) -> u64 {
+        self.get_account(address)
+            .await
+            .map_or(0, |account| account.lamports)
+    }
+
+    /// Get the total supply, and the slot it was observed at.
+    async fn get_supply(&self) -> (Slot, u64) {
+        let response = self
+            .test_validator
+            .get_async_rpc_client()
+            .supply_with_commitment(CommitmentConfig::confirmed())
+            .await
+            .unwrap();
+        (response.context.slot, response.value.total)
+    }
+
+    /// Sum the inflation rewards paid out in the slots after `start_slot`, up
+    /// to and including `end_slot`.
+    async fn get_inflation_rewards(&self, start_slot: Slot, end_slot: Slot) -> i128 {
+        let rpc_client = self.test_validator.get_async_rpc_client();
+        let slots = rpc_client
+            .get_blocks_with_commitment(
+                start_slot + 1,
+                Some(end_slot),
+                CommitmentConfig::confirmed(),
+            )
+            .await
+            .unwrap();
+
+        let mut rewards = 0;
+        for slot in slots {
+            let block = rpc_client
+                .get_block_with_config(
+                    slot,
+                    RpcBlockConfig {
+                        transaction_details: Some(TransactionDetails::None),
+                        rewards: Some(true),
+                        commitment: Some(CommitmentConfig::confirmed()),
+                        max_supported_transaction_version: Some(0),
+                        ..RpcBlockConfig::default()
+                    },
+                )
+                .await
+                .unwrap();
+            rewards += block
+                .rewards
+                .unwrap_or_default()
+                .iter()
+                .filter(|reward| {
+                    matches!(
+                        reward.reward_type,
+                        Some(RewardType::Staking | RewardType::Voting)
+                    )
+                })
+                .map(|reward| reward.lamports as i128)
+                .sum::<i128>();
+        }
+        rewards
+    }
+
+    /// Record the program and buffer balances, and the total supply.
+    pub async fn snapshot_accounting(&self, programs: &[Program]) -> AccountingSnapshot {
+        let mut balances = Vec::new();
+        for program in programs {
+            balances.push((
+                program.to_string(),
+                program.program_id(),
+                self.get_lamports(&program.program_id()).await,
+                self.get_lamports(&program.buffer_address()).await,
+            ));
+        }
+        let (slot, supply) = self.get_supply().await;
+        AccountingSnapshot {
+            slot,
+            supply,
+            balances,
+        }
+    }
+
+    /// Ensure the migrated accounts hold exactly their rent-exempt minimums,
+    /// and the change in total supply is fully accounted for.
+    pub async fn assert_accounting(&self, before: &AccountingSnapshot) {
+        let rpc_client = self.test_validator.get_async_rpc_client();
+        let mut checks = Vec::new();
+        let mut expected_delta = 0i128;
+
+        for (name, program_id, program_lamports, buffer_lamports) in &before.balances {
+            let mut funded = 0;
+            for (label, address) in [
+                ("Program", *program_id),
+                ("ProgramData", get_program_data_address(program_id)),
+            ] {
+                let account = self.get_account(&address).await;
+                let lamports = account.as_ref().map_or(0, |account| account.lamports);
+                let minimum_balance = rpc_client
+                    .get_minimum_balance_for_rent_exemption(
+                        account.as_ref().map_or(0, |account| account.data.len()),
+                    )
+                    .await
+                    .unwrap();
+                checks.push((
+                    format!("{}: {} account lamports", name, label),
+                    if lamports == minimum_balance {
+                        Ok(())
+                    } else {
+                        Err(format!(
+                            "{} lamports, expected {}",
+                            lamports, minimum_balance
+                        ))
+                    },
+                ));
+                funded += lamports;
+            }
+
+            // The builtin program account and the buffer are burned.
+            expected_delta += funded as i128;
+            expected_delta -= (*program_lamports + *buffer_lamports) as i128;
+        }
+
+        let (slot, supply) = self.get_supply().await;
+        let rewards = self.get_inflation_rewards(before.slot, slot).await;
+        let delta = supply as i128 - before.supply as i128;
+        println!();
+        println!(
+            "    Supply Before : {} (slot {})",
+            before.supply, before.slot
+        );
+        println!("    Supply After  : {} (slot {})", supply, slot);
+        println!("    Rewards       : {}", rewards);
+        println!("    Migrations    : {}", expected_delta);
+        checks.push((
+            "Total supply change".to_string(),
+            if delta == expected_delta + rewards {
+                Ok(())
+            } else {
+                Err(format!(
+                    "changed by {}, expected {}",
+                    delta,
+                    expected_delta + rewards
+                ))
+            },
+        ));
+
+        ensure!(report_checks(&checks), "Lamports were created or lost");
+    }
+}
@@ -1,12 +1,19 @@
 //! Cluster wrapper.
 
-use std::str::FromStr;
+use {
+    clap::Args,
+    solana_rpc_client::nonblocking::rpc_client::RpcClient,
+    solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel},
+    std::{future::Future, str::FromStr, time::Duration},
+};
 
 #[derive(Clone)]
 pub enum Cluster {
     Devnet,
     Testnet,
     MainnetBeta,
+    Localnet,
+    Custom(String),
 }
 
 impl std::fmt::Display for Cluster {
@@ -15,6 +22,8 @@ impl std::fmt::Display for Cluster {
             Self::Devnet => "devnet",
             Self::Testnet => "testnet",
             Self::MainnetBeta => "mainnet-beta",
+            Self::Localnet => "localnet",
+            Self::Custom(url) => url.as_str(),
         };
         write!(f, "{}", s)
     }
@@ -26,6 +35,8 @@ impl Cluster {
             Self::Devnet => "https://api.devnet.solana.com",
             Self::Testnet => "https://api.testnet.solana.com",
             Self::MainnetBeta => "https://api.mainnet-beta.solana.com",
+            Self::Localnet => "http://127.0.0.1:8899",
+            Self::Custom(url) => url,
         }
     }
 }
@@ -35,10 +46,105 @@ impl FromStr for Cluster {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
-            "devnet" => Ok(Self::Devnet),
-            "testnet" => Ok(Self::Testnet),
-            "mainnet-beta" => Ok(Self::MainnetBeta),
+            "devnet" | "d" => Ok(Self::Devnet),
+            "testnet" | "t" => Ok(Self::Testnet),
+            "mainnet-beta" | "m" => Ok(Self::MainnetBeta),
+            "localnet" | "localhost" | "l" => Ok(Self::Localnet),
+            _ if s.starts_with("http://") || s.starts_with("https://") => {
+                Ok(Self::Custom(s.to_string()))
+            }
             _ => Err(format!("Invalid cluster: {}", s)),
         }
     }
 }
+
+/// Settings for the RPC client used to talk to a cluster.
+#[derive(Args, Clone)]
+pub struct RpcArgs {
+    /// Timeout for RPC requests, in seconds.
+    #[arg(long, default_value = "30")]
+    pub rpc_timeout: u64,
+    /// Commitment level for RPC requests.
+    #[arg(long, default_value = "confirmed")]
+    pub commitment: CommitmentLevel,
+    /// Number of times to retry a failed RPC request.
+    #[arg(long, default_value = "3")]
+    pub rpc_retries: u32,
+    /// Delay before the first retry of a failed RPC request, in milliseconds.
+    /// The delay doubles after each retry.
+    #[arg(long, default_value = "500")]
+    pub rpc_backoff_ms: u64,
+}
+
+impl RpcArgs {
+    pub fn client(&self, cluster: &Cluster) -> RpcClient {
+        RpcClient::new_with_timeout_and_commitment(
+            cluster.url().to_string(),
+            Duration::from_secs(self.rpc_timeout),
+            CommitmentConfig {
+                commitment: self.commitment,
+            },
+        )
+    }
+
+    /// Run an RPC request, retrying with exponential backoff if it fails.
+    pub async fn with_retries<T, E, F, Fut>(&self, mut request: F) -> Result<T, E>
+    where
+        E: std::fmt::Display,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut backoff = Duration::from_millis(self.rpc_backoff_ms);
+        let mut attempt = 0;
+        loop {
+            match request().await {
+                Ok(value) => return Ok(value),
+                Err(err) if attempt < self.rpc_retries => {
+                    attempt += 1;
+                    println!(
+                        "RPC request failed ({}), retrying in {}ms ({}/{})...",
+                        err,
+                        backoff.as_millis(),
+                        attempt,
+                        self.rpc_retries
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str_aliases() {
+        for (alias, url) in [
+            ("d", "https://api.devnet.solana.com"),
+            ("t", "https://api.testnet.solana.com"),
+            ("m", "https://api.mainnet-beta.solana.com"),
+            ("l", "http://127.0.0.1:8899"),
+            ("localhost", "http://127.0.0.1:8899"),
+        ] {
+            assert_eq!(Cluster::from_str(alias).unwrap().url(), url);
+        }
+    }
+
+    #[test]
+    fn test_from_str_custom_url() {
+        let cluster = Cluster::from_str("https://rpc.example.com").unwrap();
+
+        assert_eq!(cluster.url(), "https://rpc.example.com");
+        assert_eq!(cluster.to_string(), "https://rpc.example.com");
+    }
+
+    #[test]
+    fn test_from_str_rejects_unknown_cluster() {
+        assert!(Cluster::from_str("rpc.example.com").is_err());
+        assert!(Cluster::from_str("devnet2").is_err());
+    }
+}
@@ -1,7 +1,13 @@
 //! Conformance testing handler.
 
 use {
-    crate::program::Program,
+    crate::{
+        deps::{fetch_protos, Dependency, DependencyCache},
+        error::{run_command, CbmtError},
+        fixture::{self, FixtureResult, Target},
+        program::Program,
+        skip::FixtureFilter,
+    },
     solana_sdk::pubkey::Pubkey,
     std::{
         path::{Path, PathBuf},
@@ -9,11 +15,8 @@ use {
     },
 };
 
-const PATH_CONFORMANCE: &str = "./solana-conformance";
-const PATH_PROGRAM_REPO: &str = "impl/program-repo";
-const PATH_SF_AGAVE: &str = "impl/solfuzz-agave";
-const PATH_TARGETS_DIR: &str = "impl/lib";
-const PATH_TEST_VECTORS: &str = "impl/test-vectors";
+/// The directory in the dependency cache conformance targets are built to.
+const TARGETS_DIRECTORY: &str = "targets";
 
 /// Conformance testing handler.
 pub struct ConformanceHandler {
@@ -21,147 +24,138 @@ pub struct ConformanceHandler {
     bpf_target_path: Option<PathBuf>,
     elf_path: PathBuf,
     fixtures_path: PathBuf,
+    offline: bool,
     program_id: Pubkey,
+    solfuzz_agave_path: PathBuf,
+    targets_path: PathBuf,
 }
 
 impl ConformanceHandler {
-    fn new(program: &Program, elf_directory: &str, fixtures_path: PathBuf) -> Self {
+    fn new(
+        program: &Program,
+        elf_directory: &str,
+        cache: &DependencyCache,
+        solfuzz_agave_path: PathBuf,
+        fixtures_path: PathBuf,
+    ) -> Self {
         Self {
             builtin_target_path: None,
             bpf_target_path: None,
             elf_path: Path::new(elf_directory).join(program.elf_name()),
             fixtures_path,
+            offline: cache.is_offline(),
             program_id: program.program_id(),
+            solfuzz_agave_path,
+            targets_path: cache.directory().join(TARGETS_DIRECTORY),
         }
     }
 
-    pub fn no_setup(program: &Program, elf_directory: &str, use_mollusk_fixtures: bool) -> Self {
-        Self::new(
+    /// Use the harness and fixtures as they are in the cache.
+    pub fn no_setup(
+        program: &Program,
+        elf_directory: &str,
+        use_mollusk_fixtures: bool,
+        cache: &DependencyCache,
+    ) -> Result<Self, CbmtError> {
+        let fixtures_path = if use_mollusk_fixtures {
+            cache
+                .path(&Dependency::program_repository(program)?)
+                .join("program")
+                .join("fuzz")
+                .join("blob")
+        } else {
+            cache
+                .path(&Dependency::test_vectors())
+                .join(firedancer_fixtures_path(program)?)
+        };
+        Ok(Self::new(
             program,
             elf_directory,
-            if use_mollusk_fixtures {
-                Path::new(PATH_CONFORMANCE)
-                    .join(PATH_PROGRAM_REPO)
-                    .join("program")
-                    .join("fuzz")
-                    .join("blob")
-            } else {
-                Path::new(PATH_CONFORMANCE)
-                    .join(PATH_TEST_VECTORS)
-                    .join(program.fixtures_path())
-            },
-        )
+            cache,
+            cache.path(&Dependency::solfuzz_agave()),
+            fixtures_path,
+        ))
     }
 
-    pub fn setup(program: &Program, elf_directory: &str, use_mollusk_fixtures: bool) -> Self {
-        // Clone harnesses.
-        git_clone(
-            "https://github.com/firedancer-io/solana-conformance.git",
-            "main",
-            Path::new(PATH_CONFORMANCE),
-        );
-        git_clone(
-            "http://github.com/buffalojoec/solfuzz-agave.git",
-            "support-feature-gate-program",
-            &Path::new(PATH_CONFORMANCE).join(PATH_SF_AGAVE),
-        );
+    /// Check out the harness and fixtures at their pinned commits.
+    pub fn setup(
+        program: &Program,
+        elf_directory: &str,
+        use_mollusk_fixtures: bool,
+        cache: &DependencyCache,
+    ) -> Result<Self, CbmtError> {
+        // Check out harness.
+        let solfuzz_agave_path = cache.checkout(&Dependency::solfuzz_agave())?;
 
         // Set up fixtures.
         let fixtures_path = if use_mollusk_fixtures {
             // Use the Mollusk-generated fixtures from the program's repository.
-            let path = Path::new(PATH_CONFORMANCE).join(PATH_PROGRAM_REPO);
-
-            git_clone(&program.repository(), "main", &path);
-
-            path.join("program").join("fuzz").join("blob")
+            cache
+                .checkout(&Dependency::program_repository(program)?)?
+                .join("program")
+                .join("fuzz")
+                .join("blob")
         } else {
             // Use the fixtures provided by Firedancer.
-            let path = Path::new(PATH_CONFORMANCE).join(PATH_TEST_VECTORS);
-
-            git_clone(
-                "https://github.com/firedancer-io/test-vectors.git",
-                "main",
-                &path,
-            );
-
-            let path = path.join(program.fixtures_path());
-
-            // Remove skipped fixtures.
-            for fix in program.skip_conformance_fixtures() {
-                Command::new("rm")
-                    .arg(path.join(fix).join(".fix"))
-                    .status()
-                    .expect("Failed to remove fixture");
-            }
-
-            path
+            cache
+                .checkout(&Dependency::test_vectors())?
+                .join(firedancer_fixtures_path(program)?)
         };
 
-        // Fetch protos.
-        Command::new("make")
-            .current_dir(PATH_CONFORMANCE)
-            .arg("-j")
-            .arg("-C")
-            .arg(PATH_SF_AGAVE)
-            .arg("fetch_proto")
-            .status()
-            .expect("Failed to fetch protobufs");
-
-        // Build environment.
-        Command::new("bash")
-            .current_dir(PATH_CONFORMANCE)
-            .arg("install_ubuntu_lite.sh")
-            .status()
-            .expect("Failed to install dependencies");
+        // Fetch protos, needed to build the targets. Offline, they must
+        // have been fetched by `fetch-deps`.
+        if !cache.is_offline() {
+            fetch_protos(&solfuzz_agave_path)?;
+        }
 
-        Self::new(program, elf_directory, fixtures_path)
+        Ok(Self::new(
+            program,
+            elf_directory,
+            cache,
+            solfuzz_agave_path,
+            fixtures_path,
+        ))
     }
 
-    pub fn build_conformance_target_builtin(&mut self) {
-        make_targets_dir();
+    pub fn build_conformance_target_builtin(&mut self) -> Result<(), CbmtError> {
+        make_targets_dir(&self.targets_path)?;
 
-        let manifest_path = Path::new(PATH_CONFORMANCE)
-            .join(PATH_SF_AGAVE)
-            .join("Cargo.toml");
-        let src_path = Path::new(PATH_CONFORMANCE)
-            .join(PATH_SF_AGAVE)
+        let manifest_path = self.solfuzz_agave_path.join("Cargo.toml");
+        let src_path = self
+            .solfuzz_agave_path
             .join("target/x86_64-unknown-linux-gnu/release/libsolfuzz_agave.so");
-        let target_path = Path::new(PATH_CONFORMANCE)
-            .join(PATH_TARGETS_DIR)
-            .join("builtin.so");
-
-        Command::new("cargo")
-            .arg("build")
-            .arg("--manifest-path")
-            .arg(&manifest_path)
-            .arg("--lib")
-            .arg("--release")
-            .arg("--target")
-            .arg("x86_64-unknown-linux-gnu")
-            .status()
-            .expect("Failed to build target");
-
-        mv(&src_path, &target_path);
+        let target_path = self.targets_path.join("builtin.so");
+
+        run_command(
+            Command::new("cargo")
+                .arg("build")
+                .arg("--manifest-path")
+                .arg(&manifest_path)
+                .arg("--lib")
+                .arg("--release")
+                .arg("--target")
+                .arg("x86_64-unknown-linux-gnu")
+                .args(self.offline.then_some("--offline")),
+            CbmtError::Build,
+        )?;
+
+        mv(&src_path, &target_path)?;
 
         self.builtin_target_path = Some(target_path);
+        Ok(())
     }
 
-    pub fn build_conformance_target_bpf(&mut self, conformance_mode: bool) {
-        make_targets_dir();
+    pub fn build_conformance_target_bpf(
+        &mut self,
+        conformance_mode: bool,
+    ) -> Result<(), CbmtError> {
+        make_targets_dir(&self.targets_path)?;
 
-        let manifest_path = Path::new(PATH_CONFORMANCE)
-            .join(PATH_SF_AGAVE)
-            .join("Cargo.toml");
-        let src_path = Path::new(PATH_CONFORMANCE)
-            .join(PATH_SF_AGAVE)
+        let manifest_path = self.solfuzz_agave_path.join("Cargo.toml");
+        let src_path = self
+            .solfuzz_agave_path
             .join("target/x86_64-unknown-linux-gnu/release/libsolfuzz_agave.so");
-        let target_path = Path::new(PATH_CONFORMANCE)
-            .join(PATH_TARGETS_DIR)
-            .join("bpf.so");
-
-        std::env::set_var("CORE_BPF_PROGRAM_ID", self.program_id.to_string());
-        std::env::set_var("CORE_BPF_TARGET", cwd().join(&self.elf_path));
-        std::env::set_var("FORCE_RECOMPILE", "true");
+        let target_path = self.targets_path.join("bpf.so");
 
         let feature_flag = if conformance_mode {
             "core-bpf-conformance"
@@ -169,128 +163,81 @@ impl ConformanceHandler {
             "core-bpf"
         };
 
-        Command::new("cargo")
-            .arg("build")
-            .arg("--manifest-path")
-            .arg(&manifest_path)
-            .arg("--lib")
-            .arg("--release")
-            .arg("--target")
-            .arg("x86_64-unknown-linux-gnu")
-            .arg("--features")
-            .arg(feature_flag)
-            .status()
-            .expect("Failed to build target");
-
-        mv(&src_path, &target_path);
-
-        std::env::remove_var("CORE_BPF_PROGRAM_ID");
-        std::env::remove_var("CORE_BPF_TARGET");
-        std::env::remove_var("FORCE_RECOMPILE");
+        run_command(
+            Command::new("cargo")
+                .env("CORE_BPF_PROGRAM_ID", self.program_id.to_string())
+                .env("CORE_BPF_TARGET", cwd()?.join(&self.elf_path))
+                .env("FORCE_RECOMPILE", "true")
+                .arg("build")
+                .arg("--manifest-path")
+                .arg(&manifest_path)
+                .arg("--lib")
+                .arg("--release")
+                .arg("--target")
+                .arg("x86_64-unknown-linux-gnu")
+                .arg("--features")
+                .arg(feature_flag)
+                .args(self.offline.then_some("--offline")),
+            CbmtError::Build,
+        )?;
+
+        mv(&src_path, &target_path)?;
 
         self.bpf_target_path = Some(target_path);
+        Ok(())
     }
 
-    pub fn run_fixtures(&self) {
-        let i_path = cwd().join(&self.fixtures_path);
-        let t_path = cwd().join(
-            self.bpf_target_path
-                .as_ref()
-                .expect("BPF target was not built"),
-        );
+    /// Run every fixture against the BPF target, comparing the effects to
+    /// the fixture's expected effects.
+    pub fn run_fixtures(&self, filter: &FixtureFilter) -> Result<Vec<FixtureResult>, CbmtError> {
+        let target = load_target(self.bpf_target_path.as_deref(), "BPF")?;
 
-        let output = Command::new("bash")
-        .arg("-c")
-        .arg(format!(
-            "cd {} && source test_suite_env/bin/activate && solana-test-suite exec-fixtures -i {} -t {}",
-            PATH_CONFORMANCE,
-            i_path.display(),
-            t_path.display()
-        ))
-        .output()
-        .expect("Failed to run fixtures tests");
+        fixture::run_fixtures(&self.fixtures_path, filter, &target).map_err(CbmtError::Harness)
+    }
 
-        let output = core::str::from_utf8(&output.stdout).unwrap();
-        println!("{}", output);
+    /// Run every fixture's input against both the builtin and BPF targets,
+    /// comparing their effects.
+    pub fn run_conformance(&self, filter: &FixtureFilter) -> Result<Vec<FixtureResult>, CbmtError> {
+        let builtin_target = load_target(self.builtin_target_path.as_deref(), "Builtin")?;
+        let bpf_target = load_target(self.bpf_target_path.as_deref(), "BPF")?;
 
-        if output.contains("Failed tests:") {
-            panic!("Test failed! Oh no!");
-        }
+        fixture::run_conformance(&self.fixtures_path, filter, &builtin_target, &bpf_target)
+            .map_err(CbmtError::Harness)
     }
+}
 
-    pub fn run_conformance(&self) {
-        let i_path = cwd().join(&self.fixtures_path);
-        let s_path = cwd().join(
-            self.builtin_target_path
-                .as_ref()
-                .expect("Builtin target was not built"),
-        );
-        let t_path = cwd().join(
-            self.bpf_target_path
-                .as_ref()
-                .expect("BPF target was not built"),
-        );
-
-        Command::new("bash")
-        .arg("-c")
-        .arg(format!(
-            "cd {} && source test_suite_env/bin/activate && solana-test-suite run-tests -i {} -s {} -t {}",
-            PATH_CONFORMANCE,
-            i_path.display(),
-            s_path.display(),
-            t_path.display()
+fn firedancer_fixtures_path(program: &Program) -> Result<&str, CbmtError> {
+    program.fixtures_path().ok_or_else(|| {
+        CbmtError::Config(format!(
+            "Program `{}` has no Firedancer fixtures path",
+            program.name()
         ))
-        .status()
-        .expect("Failed to run conformance tests");
-
-        let failed_protos_path = Path::new(PATH_CONFORMANCE).join("test_results/failed_protobufs");
-        if failed_protos_path.is_dir()
-            && !std::fs::read_dir(failed_protos_path)
-                .map(|mut entries| entries.next().is_none())
-                .unwrap_or(false)
-        {
-            panic!("Test failed! Oh no!");
-        }
-    }
+    })
 }
 
-fn cwd() -> PathBuf {
-    std::env::current_dir().expect("Failed to get current working directory")
+fn load_target(path: Option<&Path>, name: &str) -> Result<Target, CbmtError> {
+    let path = path.ok_or_else(|| CbmtError::Harness(format!("{} target was not built", name)))?;
+    Target::load(path).map_err(CbmtError::Harness)
 }
 
-fn git_clone(url: &str, branch: &str, out_dir: &Path) {
-    if std::fs::metadata(out_dir).is_ok() {
-        Command::new("git")
-            .arg("-C")
-            .arg(out_dir)
-            .arg("pull")
-            .status()
-            .expect("Failed to pull repo");
-    } else {
-        Command::new("git")
-            .arg("clone")
-            .arg("--branch")
-            .arg(branch)
-            .arg(url)
-            .arg(out_dir)
-            .status()
-            .expect("Failed to clone repo");
-    }
+fn cwd() -> Result<PathBuf, CbmtError> {
+    std::env::current_dir().map_err(|err| {
+        CbmtError::Harness(format!("Failed to get current working directory: {}", err))
+    })
 }
 
-fn make_targets_dir() {
-    Command::new("mkdir")
-        .current_dir(PATH_CONFORMANCE)
-        .arg("-p")
-        .arg(PATH_TARGETS_DIR)
-        .status()
-        .expect("Failed to create directory");
+fn make_targets_dir(path: &Path) -> Result<(), CbmtError> {
+    std::fs::create_dir_all(path)
+        .map_err(|err| CbmtError::Harness(format!("Failed to create {}: {}", path.display(), err)))
 }
 
-fn mv(src: &Path, dest: &Path) {
-    Command::new("mv")
-        .arg(src)
-        .arg(dest)
-        .status()
-        .expect("Failed to move file");
+fn mv(src: &Path, dest: &Path) -> Result<(), CbmtError> {
+    std::fs::rename(src, dest).map_err(|err| {
+        CbmtError::Harness(format!(
+            "Failed to move {} to {}: {}",
+            src.display(),
+            dest.display(),
+            err
+        ))
+    })
 }
@@ -0,0 +1,250 @@
+//! Compute unit comparison.
+//!
+//! Runs the program's smoke suite against the builtin, migrates it, then runs
+//! the suite again against the BPF program. Each step is a single
+//! transaction, so the compute units consumed by the builtin and BPF versions
+//! can be compared step by step.
+
+use {
+    crate::{
+        elf::ElfSource,
+        error::CbmtError,
+        output::{output, title_cu},
+        program::Program,
+        report,
+        smoke::has_smoke_suite,
+        stub::{cargo_build_sbf, MANIFEST_PATH_ACTIVATOR},
+        validator::{MigrationTarget, ValidatorArgs, ValidatorContext},
+        write_elf_to_file, ELF_DIRECTORY,
+    },
+    clap::Args,
+};
+
+/// Limits on the compute units consumed by the BPF program.
+#[derive(Args)]
+pub struct CuThresholds {
+    /// Flag steps where the BPF program consumes more than this many compute
+    /// units.
+    #[arg(long)]
+    pub max_cu: Option<u64>,
+    /// Flag steps where the BPF program consumes more than this multiple of
+    /// the builtin's compute units.
+    #[arg(long)]
+    pub max_cu_ratio: Option<f64>,
+}
+
+impl CuThresholds {
+    /// Why the BPF cost of a step exceeds the thresholds, if it does.
+    fn exceeded(&self, builtin: u64, bpf: u64) -> Option<String> {
+        if let Some(max_cu) = self.max_cu {
+            if bpf > max_cu {
+                return Some(format!("more than {} CUs", max_cu));
+            }
+        }
+        if let Some(max_cu_ratio) = self.max_cu_ratio {
+            if bpf as f64 > builtin as f64 * max_cu_ratio {
+                return Some(format!("more than {}x the builtin", max_cu_ratio));
+            }
+        }
+        None
+    }
+}
+
+pub async fn run_cu_comparison(
+    program: &Program,
+    source: &ElfSource,
+    validator_args: &ValidatorArgs,
+    thresholds: &CuThresholds,
+) -> Result<(), CbmtError> {
+    if program.program_id() == solana_sdk::feature::id() {
+        return Err(CbmtError::Config(
+            "The Feature Gate program has no builtin to compare against".to_string(),
+        ));
+    }
+    if !has_smoke_suite(&program.program_id()) {
+        return Err(CbmtError::Config(format!("No smoke suite for {}", program)));
+    }
+
+    title_cu(&source.to_string(), &source.buffer_address(program));
+
+    output("Bulding programs...");
+    cargo_build_sbf(MANIFEST_PATH_ACTIVATOR)?;
+
+    output(&format!("Loading ELF for {} from {}...", program, source));
+    let elf = source.load(program).await?;
+    write_elf_to_file(elf, &program.elf_name())?;
+
+    output("Starting test validator...");
+    let context = ValidatorContext::start(
+        &[MigrationTarget {
+            feature_id: program.feature_gate(),
+            buffer_address: program.buffer_address(),
+            elf_name: &program.elf_stem(),
+            failure_mode: None,
+        }],
+        ELF_DIRECTORY,
+        validator_args,
+        /* feature_gate_stand_in */ false,
+        /* accounting */ false,
+    )
+    .await;
+
+    output(&format!(
+        "Checking to see if {} is currently a builtin...",
+        program
+    ));
+    context
+        .assert_program_is_builtin(&program.program_id())
+        .await;
+    output("It is.");
+
+    output(&format!("Running smoke tests on builtin {}...", program));
+    let builtin = get_compute_units(&context, program).await?;
+
+    output(&format!(
+        "Activating feature {} for {}...",
+        program.feature_gate(),
+        program
+    ));
+    context.activate_feature(&program.feature_gate()).await;
+    context.wait_for_next_epoch().await;
+    context.wait_for_next_slot().await;
+
+    output(&format!(
+        "Checking to see if {} is now a BPF program...",
+        program
+    ));
+    context
+        .assert_program_is_bpf(
+            &program.program_id(),
+            &program.feature_gate(),
+            &program.buffer_address(),
+        )
+        .await;
+    output("It is.");
+
+    output(&format!("Running smoke tests on BPF {}...", program));
+    let bpf = get_compute_units(&context, program).await?;
+    let steps = pair_steps(&builtin, &bpf)?;
+
+    println!();
+    println!(
+        "      {:<32} {:>10} {:>10} {:>8}",
+        "Step", "Builtin", "BPF", "Ratio"
+    );
+    let mut exceeded = 0;
+    for (name, builtin, bpf) in steps {
+        let result = thresholds.exceeded(builtin, bpf).map_or(Ok(()), Err);
+        report::record(
+            format!("{}: {} builtin, {} BPF", name, builtin, bpf),
+            &result,
+        );
+        let flag = match result {
+            Ok(()) => String::new(),
+            Err(reason) => {
+                exceeded += 1;
+                format!("  EXCEEDED ({})", reason)
+            }
+        };
+        println!(
+            "      {:<32} {:>10} {:>10} {:>8}{}",
+            name,
+            builtin,
+            bpf,
+            format_ratio(builtin, bpf),
+            flag
+        );
+    }
+    println!();
+
+    if exceeded > 0 {
+        return Err(CbmtError::Validation(format!(
+            "{} steps exceed the compute unit thresholds",
+            exceeded
+        )));
+    }
+
+    output("Comparison complete! Woohoo!");
+
+    Ok(())
+}
+
+/// Pair up the compute units consumed by each step of the builtin and BPF
+/// runs, which must have run the same steps in the same order.
+fn pair_steps(
+    builtin: &[(&'static str, u64)],
+    bpf: &[(&'static str, u64)],
+) -> Result<Vec<(&'static str, u64, u64)>, CbmtError> {
+    if builtin.len() != bpf.len() {
+        return Err(CbmtError::Harness(format!(
+            "The builtin ran {} smoke test steps, but the BPF program ran {}",
+            builtin.len(),
+            bpf.len()
+        )));
+    }
+    builtin
+        .iter()
+        .zip(bpf)
+        .map(|((builtin_name, builtin), (bpf_name, bpf))| {
+            if builtin_name != bpf_name {
+                return Err(CbmtError::Harness(format!(
+                    "Smoke test step mismatch: builtin ran `{}`, BPF program ran `{}`",
+                    builtin_name, bpf_name
+                )));
+            }
+            Ok((*builtin_name, *builtin, *bpf))
+        })
+        .collect()
+}
+
+/// The BPF cost of a step as a multiple of the builtin's, or `n/a` if the
+/// builtin consumed no compute units.
+fn format_ratio(builtin: u64, bpf: u64) -> String {
+    if builtin == 0 {
+        return "n/a".to_string();
+    }
+    format!("{:.2}x", bpf as f64 / builtin as f64)
+}
+
+/// Run the smoke suite, returning the compute units consumed by each step.
+async fn get_compute_units(
+    context: &ValidatorContext,
+    program: &Program,
+) -> Result<Vec<(&'static str, u64)>, CbmtError> {
+    let steps = context.run_smoke_tests(&program.program_id()).await;
+
+    let mut compute_units = Vec::new();
+    for (name, signature) in steps {
+        let outcome = context.get_transaction_outcome(&signature).await;
+        let consumed = outcome.compute_units_consumed.ok_or_else(|| {
+            CbmtError::Harness(format!(
+                "Smoke test step `{}` reported no compute units consumed",
+                name
+            ))
+        })?;
+        compute_units.push((name, consumed));
+    }
+    Ok(compute_units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pair_steps() {
+        let builtin = [("create", 100), ("close", 50)];
+
+        let paired = pair_steps(&builtin, &[("create", 300), ("close", 80)]).unwrap();
+        assert_eq!(paired, vec![("create", 100, 300), ("close", 50, 80)]);
+
+        assert!(pair_steps(&builtin, &[("create", 300)]).is_err());
+        assert!(pair_steps(&builtin, &[("create", 300), ("extend", 80)]).is_err());
+    }
+
+    #[test]
+    fn test_format_ratio() {
+        assert_eq!(format_ratio(100, 250), "2.50x");
+        assert_eq!(format_ratio(0, 250), "n/a");
+    }
+}
@@ -0,0 +1,349 @@
+//! Harness dependency cache.
+//!
+//! The fixtures and conformance tests build and run against several git
+//! repositories. Each is pinned to a commit in `cbmt-deps.lock`:
+//!
+//! ```toml
+//! [[dependency]]
+//! name = "solfuzz-agave"
+//! url = "http://github.com/buffalojoec/solfuzz-agave.git"
+//! branch = "support-feature-gate-program"
+//! commit = "<commit hash>"
+//! ```
+//!
+//! Dependencies are checked out at their pinned commits in a cache directory,
+//! so every run tests the same commits, and runs can be made offline once the
+//! cache is populated with `cbmt fetch-deps`.
+
+use {
+    crate::{
+        error::{command_output, run_command, CbmtError},
+        output::{output, warning},
+        program::Program,
+    },
+    clap::Args,
+    serde::{Deserialize, Serialize},
+    std::{
+        path::{Path, PathBuf},
+        process::Command,
+    },
+};
+
+/// The lockfile pinning every dependency, in the working directory.
+pub const LOCKFILE_PATH: &str = "cbmt-deps.lock";
+
+/// The solfuzz-agave harness, built into the conformance targets.
+pub const DEPENDENCY_SOLFUZZ_AGAVE: &str = "solfuzz-agave";
+/// Firedancer's fixtures.
+pub const DEPENDENCY_TEST_VECTORS: &str = "test-vectors";
+
+const DEFAULT_CACHE_DIRECTORY: &str = ".cbmt-cache";
+
+/// A git repository the harness depends on, tracking a branch.
+pub struct Dependency {
+    pub name: String,
+    pub url: String,
+    pub branch: String,
+}
+
+impl Dependency {
+    fn new(name: &str, url: &str, branch: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            url: url.to_string(),
+            branch: branch.to_string(),
+        }
+    }
+
+    /// The solfuzz-agave harness.
+    pub fn solfuzz_agave() -> Self {
+        Self::new(
+            DEPENDENCY_SOLFUZZ_AGAVE,
+            "http://github.com/buffalojoec/solfuzz-agave.git",
+            "support-feature-gate-program",
+        )
+    }
+
+    /// Firedancer's fixtures.
+    pub fn test_vectors() -> Self {
+        Self::new(
+            DEPENDENCY_TEST_VECTORS,
+            "https://github.com/firedancer-io/test-vectors.git",
+            "main",
+        )
+    }
+
+    /// The program's repository, containing its Mollusk fixtures.
+    pub fn program_repository(program: &Program) -> Result<Self, CbmtError> {
+        let url = program.repository().ok_or_else(|| {
+            CbmtError::Config(format!("Program `{}` has no repository", program.name()))
+        })?;
+        Ok(Self::new(
+            &format!("{}-program", program.name()),
+            url,
+            "main",
+        ))
+    }
+}
+
+/// Every dependency of the harness, including the repository of each program
+/// that has one.
+pub fn harness_dependencies(programs: &[Program]) -> Vec<Dependency> {
+    let mut dependencies = vec![Dependency::solfuzz_agave(), Dependency::test_vectors()];
+    dependencies.extend(
+        programs
+            .iter()
+            .filter_map(|program| Dependency::program_repository(program).ok()),
+    );
+    dependencies
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct Lockfile {
+    #[serde(default)]
+    dependency: Vec<LockedDependency>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct LockedDependency {
+    name: String,
+    url: String,
+    branch: String,
+    commit: String,
+}
+
+/// Where harness dependencies are cached.
+#[derive(Args)]
+pub struct CacheArgs {
+    /// The directory harness dependencies are checked out to.
+    #[arg(long, default_value = DEFAULT_CACHE_DIRECTORY)]
+    pub cache_dir: PathBuf,
+    /// Only use the dependencies already in the cache, without fetching
+    /// anything.
+    #[arg(long)]
+    pub offline: bool,
+}
+
+/// The cache of harness dependencies, checked out at their pinned commits.
+pub struct DependencyCache {
+    directory: PathBuf,
+    offline: bool,
+    lockfile: Lockfile,
+}
+
+impl DependencyCache {
+    /// Open the cache, reading the pins from the lockfile, if it exists.
+    pub fn open(args: &CacheArgs) -> Result<Self, CbmtError> {
+        let lockfile = if Path::new(LOCKFILE_PATH).exists() {
+            let contents = std::fs::read_to_string(LOCKFILE_PATH).map_err(|err| {
+                CbmtError::Config(format!("Failed to read {}: {}", LOCKFILE_PATH, err))
+            })?;
+            toml::from_str(&contents).map_err(|err| {
+                CbmtError::Config(format!("Failed to parse {}: {}", LOCKFILE_PATH, err))
+            })?
+        } else {
+            Lockfile::default()
+        };
+        Ok(Self {
+            directory: args.cache_dir.clone(),
+            offline: args.offline,
+            lockfile,
+        })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// The dependency's checkout in the cache, whether or not it exists.
+    pub fn path(&self, dependency: &Dependency) -> PathBuf {
+        self.directory.join(&dependency.name)
+    }
+
+    /// Check out the dependency at its pinned commit, fetching it unless
+    /// offline, and return the path to the checkout. A dependency missing from
+    /// the lockfile is checked out at the head of its branch instead.
+    pub fn checkout(&self, dependency: &Dependency) -> Result<PathBuf, CbmtError> {
+        let Some(locked) = self
+            .lockfile
+            .dependency
+            .iter()
+            .find(|locked| locked.name == dependency.name)
+        else {
+            return self.checkout_branch(dependency);
+        };
+        if locked.url != dependency.url {
+            return Err(CbmtError::Config(format!(
+                "`{}` is pinned to {}, not {}. Run `cbmt fetch-deps --update` to pin it again.",
+                dependency.name, locked.url, dependency.url
+            )));
+        }
+
+        let path = self.path(dependency);
+        let path_str = path.to_string_lossy();
+        if path.exists() && git(&["-C", &path_str, "rev-parse", "HEAD"])? == locked.commit {
+            return Ok(path);
+        }
+
+        if self.offline {
+            let commit = format!("{}^{{commit}}", locked.commit);
+            if !path.exists() || git(&["-C", &path_str, "cat-file", "-e", &commit]).is_err() {
+                return Err(CbmtError::Harness(format!(
+                    "`{}` at {} is not in the cache at {}. Run `cbmt fetch-deps` before running \
+                     offline.",
+                    dependency.name,
+                    locked.commit,
+                    self.directory.display()
+                )));
+            }
+        } else {
+            if !path.exists() {
+                git(&["clone", "--no-checkout", &locked.url, &path_str])?;
+            }
+            git(&["-C", &path_str, "fetch", "origin", &locked.commit])?;
+        }
+        git(&["-C", &path_str, "checkout", "--detach", &locked.commit])?;
+
+        Ok(path)
+    }
+
+    /// Check out an unpinned dependency at the head of its branch, or use the
+    /// cached checkout as is when offline.
+    fn checkout_branch(&self, dependency: &Dependency) -> Result<PathBuf, CbmtError> {
+        warning(&format!(
+            "`{}` is not pinned in {}, so the head of its `{}` branch is used and runs are NOT \
+             reproducible. Run `cbmt fetch-deps --update` and commit {} to pin it.",
+            dependency.name, LOCKFILE_PATH, dependency.branch, LOCKFILE_PATH
+        ));
+
+        let path = self.path(dependency);
+        let path_str = path.to_string_lossy();
+        if self.offline {
+            if !path.exists() {
+                return Err(CbmtError::Harness(format!(
+                    "`{}` is not in the cache at {}. Run `cbmt fetch-deps` before running \
+                     offline.",
+                    dependency.name,
+                    self.directory.display()
+                )));
+            }
+            return Ok(path);
+        }
+
+        if !path.exists() {
+            git(&["clone", "--no-checkout", &dependency.url, &path_str])?;
+        }
+        git(&["-C", &path_str, "fetch", "origin", &dependency.branch])?;
+        git(&["-C", &path_str, "checkout", "--detach", "FETCH_HEAD"])?;
+
+        Ok(path)
+    }
+}
+
+/// Check out every dependency in the cache, first pinning each to the head of
+/// its branch if updating.
+pub fn fetch_deps(programs: &[Program], args: &CacheArgs, update: bool) -> Result<(), CbmtError> {
+    let dependencies = harness_dependencies(programs);
+
+    if update {
+        let mut lockfile = Lockfile::default();
+        for dependency in &dependencies {
+            output(&format!(
+                "Resolving {} {}...",
+                dependency.name, dependency.branch
+            ));
+            let commit = resolve_branch(dependency)?;
+            output(&format!("Pinned to {}.", commit));
+            lockfile.dependency.push(LockedDependency {
+                name: dependency.name.clone(),
+                url: dependency.url.clone(),
+                branch: dependency.branch.clone(),
+                commit,
+            });
+        }
+        write_lockfile(&lockfile)?;
+    }
+
+    let cache = DependencyCache::open(args)?;
+    for dependency in &dependencies {
+        output(&format!("Checking out {}...", dependency.name));
+        cache.checkout(dependency)?;
+    }
+
+    if !cache.is_offline() {
+        let solfuzz_agave = cache.path(&Dependency::solfuzz_agave());
+
+        output("Fetching protobufs...");
+        fetch_protos(&solfuzz_agave)?;
+
+        output("Fetching crates...");
+        run_command(
+            Command::new("cargo")
+                .arg("fetch")
+                .arg("--manifest-path")
+                .arg(solfuzz_agave.join("Cargo.toml")),
+            CbmtError::Harness,
+        )?;
+    }
+
+    output(&format!(
+        "Dependencies cached in {}! Woohoo!",
+        cache.directory.display()
+    ));
+
+    Ok(())
+}
+
+/// Fetch the protobufs solfuzz-agave needs to build.
+pub fn fetch_protos(solfuzz_agave: &Path) -> Result<(), CbmtError> {
+    run_command(
+        Command::new("make")
+            .arg("-j")
+            .arg("-C")
+            .arg(solfuzz_agave)
+            .arg("fetch_proto"),
+        CbmtError::Harness,
+    )
+}
+
+fn resolve_branch(dependency: &Dependency) -> Result<String, CbmtError> {
+    let refs = git(&[
+        "ls-remote",
+        &dependency.url,
+        &format!("refs/heads/{}", dependency.branch),
+    ])?;
+    refs.split_whitespace()
+        .next()
+        .map(ToString::to_string)
+        .ok_or_else(|| {
+            CbmtError::Harness(format!(
+                "Branch `{}` not found in {}",
+                dependency.branch, dependency.url
+            ))
+        })
+}
+
+fn write_lockfile(lockfile: &Lockfile) -> Result<(), CbmtError> {
+    let contents = toml::to_string_pretty(lockfile).map_err(|err| {
+        CbmtError::Harness(format!("Failed to serialize {}: {}", LOCKFILE_PATH, err))
+    })?;
+    std::fs::write(
+        LOCKFILE_PATH,
+        format!(
+            "# Pins every harness dependency. Generated by `cbmt fetch-deps --update`.\n\n{}",
+            contents
+        ),
+    )
+    .map_err(|err| CbmtError::Harness(format!("Failed to write {}: {}", LOCKFILE_PATH, err)))
+}
+
+/// Run git, returning its trimmed output.
+fn git(args: &[&str]) -> Result<String, CbmtError> {
+    command_output(Command::new("git").args(args), CbmtError::Harness)
+}
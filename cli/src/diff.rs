@@ -0,0 +1,142 @@
+//! ELF diffing.
+//!
+//! Compares two program ELFs, e.g. the buffers deployed to two different
+//! clusters, section by section and instruction by instruction.
+
+use {
+    crate::{inspect::disassemble, verify::sha256_hex},
+    goblin::elf::{section_header::SHT_NOBITS, Elf},
+    similar::TextDiff,
+    std::collections::{BTreeMap, BTreeSet},
+};
+
+/// A section's size and content hash.
+struct SectionSummary {
+    size: u64,
+    hash: String,
+}
+
+fn summarize_sections(elf: &[u8]) -> Result<BTreeMap<String, SectionSummary>, String> {
+    let parsed = Elf::parse(elf).map_err(|err| format!("Failed to parse ELF: {}", err))?;
+    Ok(parsed
+        .section_headers
+        .iter()
+        .filter_map(|section| {
+            let name = parsed.shdr_strtab.get_at(section.sh_name)?;
+            if name.is_empty() {
+                return None;
+            }
+            let data = if section.sh_type == SHT_NOBITS {
+                &[][..]
+            } else {
+                let start = section.sh_offset as usize;
+                let end = start.saturating_add(section.sh_size as usize);
+                elf.get(start..end).unwrap_or_default()
+            };
+            Some((
+                name.to_string(),
+                SectionSummary {
+                    size: section.sh_size,
+                    hash: sha256_hex(data),
+                },
+            ))
+        })
+        .collect())
+}
+
+/// Compare two ELFs, printing a report. Returns whether they are identical.
+pub fn diff_elfs(from_label: &str, from: &[u8], to_label: &str, to: &[u8]) -> Result<bool, String> {
+    let from_hash = sha256_hex(from);
+    let to_hash = sha256_hex(to);
+
+    println!();
+    println!(
+        "    {:<24} {} ({} bytes)",
+        from_label,
+        from_hash,
+        from.len()
+    );
+    println!("    {:<24} {} ({} bytes)", to_label, to_hash, to.len());
+    println!();
+
+    if from_hash == to_hash {
+        return Ok(true);
+    }
+
+    let from_sections = summarize_sections(from)?;
+    let to_sections = summarize_sections(to)?;
+
+    println!("    Sections:");
+    let names = from_sections
+        .keys()
+        .chain(to_sections.keys())
+        .collect::<BTreeSet<_>>();
+    for name in names {
+        let (from_size, to_size, status) = match (from_sections.get(name), to_sections.get(name)) {
+            (Some(a), Some(b)) => (
+                a.size.to_string(),
+                b.size.to_string(),
+                if a.hash == b.hash {
+                    "identical"
+                } else {
+                    "DIFFERS"
+                },
+            ),
+            (Some(a), None) => (a.size.to_string(), "-".to_string(), "ONLY IN FROM"),
+            (None, Some(b)) => ("-".to_string(), b.size.to_string(), "ONLY IN TO"),
+            (None, None) => unreachable!(),
+        };
+        println!(
+            "      {:<24} {:>10} {:>10}  {}",
+            name, from_size, to_size, status
+        );
+    }
+    println!();
+
+    match (disassemble(from), disassemble(to)) {
+        (Ok(from_asm), Ok(to_asm)) => {
+            println!("    Disassembly diff:");
+            println!();
+            let diff = TextDiff::from_lines(&from_asm, &to_asm);
+            print!(
+                "{}",
+                diff.unified_diff()
+                    .context_radius(3)
+                    .header(from_label, to_label)
+            );
+            println!();
+        }
+        (Err(err), _) | (_, Err(err)) => {
+            println!("    Skipping disassembly diff: {}", err);
+            println!();
+        }
+    }
+
+    Ok(false)
+}
+
+/// Compare the raw data two ELFs were loaded from, including any padding
+/// after them, printing their hashes if they differ. Returns whether they are
+/// identical.
+pub fn diff_raw(from_label: &str, from: &[u8], to_label: &str, to: &[u8]) -> bool {
+    if from == to {
+        return true;
+    }
+
+    println!("    Raw data, including padding:");
+    println!(
+        "      {:<22} {} ({} bytes)",
+        from_label,
+        sha256_hex(from),
+        from.len()
+    );
+    println!(
+        "      {:<22} {} ({} bytes)",
+        to_label,
+        sha256_hex(to),
+        to.len()
+    );
+    println!();
+
+    false
+}
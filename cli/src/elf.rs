@@ -0,0 +1,169 @@
+//! ELF source wrapper.
+
+use {
+    crate::{
+        cluster::{Cluster, RpcArgs},
+        error::CbmtError,
+        program::Program,
+        verify::{verify_buffer_account, verify_elf},
+    },
+    clap::Args,
+    serde::Deserialize,
+    solana_account_decoder::UiAccount,
+    solana_sdk::{
+        account::Account, bpf_loader_upgradeable::UpgradeableLoaderState, pubkey::Pubkey,
+    },
+    std::path::{Path, PathBuf},
+};
+
+/// Flags selecting where to load the program ELF from.
+#[derive(Args)]
+pub struct ElfSourceArgs {
+    /// The cluster to clone the buffer account data from.
+    #[arg(short, long, default_value = "mainnet-beta")]
+    pub cluster: Cluster,
+    /// Path to a local ELF file to use instead of cloning the buffer account.
+    #[arg(long, conflicts_with = "buffer_json")]
+    pub elf: Option<PathBuf>,
+    /// Path to a buffer account dump (`solana account --output json`) to use
+    /// instead of cloning the buffer account.
+    #[arg(long)]
+    pub buffer_json: Option<PathBuf>,
+    #[command(flatten)]
+    pub rpc: RpcArgs,
+}
+
+impl ElfSourceArgs {
+    pub fn source(self) -> ElfSource {
+        if let Some(path) = self.elf {
+            ElfSource::Elf(path)
+        } else if let Some(path) = self.buffer_json {
+            ElfSource::BufferJson(path)
+        } else {
+            ElfSource::Cluster(self.cluster, self.rpc)
+        }
+    }
+}
+
+/// Where to load the program ELF from.
+pub enum ElfSource {
+    /// Clone the buffer account from a cluster.
+    Cluster(Cluster, RpcArgs),
+    /// Read a local ELF file.
+    Elf(PathBuf),
+    /// Read a buffer account dump.
+    BufferJson(PathBuf),
+}
+
+impl std::fmt::Display for ElfSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cluster(cluster, _) => write!(f, "{}", cluster),
+            Self::Elf(path) => write!(f, "{}", path.display()),
+            Self::BufferJson(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl ElfSource {
+    /// The buffer address the ELF is sourced from.
+    pub fn buffer_address(&self, program: &Program) -> Pubkey {
+        match self {
+            Self::Cluster(cluster, _) => program.buffer_address_on(cluster),
+            _ => program.buffer_address(),
+        }
+    }
+
+    /// Load the ELF, verifying it against the program's pinned buffer
+    /// authority and ELF hash.
+    pub async fn load(&self, program: &Program) -> Result<Vec<u8>, CbmtError> {
+        Ok(self.load_with_padding(program).await?.0)
+    }
+
+    /// Load the ELF, verifying it as with [`Self::load`], along with the raw
+    /// data it was read from, including any padding after it: the buffer
+    /// account's data after its metadata, or the ELF file's contents.
+    pub async fn load_with_padding(
+        &self,
+        program: &Program,
+    ) -> Result<(Vec<u8>, Vec<u8>), CbmtError> {
+        match self {
+            Self::Cluster(cluster, rpc) => {
+                let account = clone_buffer_account(cluster, rpc, program).await?;
+                verify_buffer_data(&account, program)
+            }
+            Self::Elf(path) => {
+                let elf = std::fs::read(path).map_err(|err| {
+                    CbmtError::Harness(format!(
+                        "Failed to read ELF file {}: {}",
+                        path.display(),
+                        err
+                    ))
+                })?;
+                let verified = verify_elf(&elf, program).map_err(CbmtError::Validation)?;
+                Ok((verified, elf))
+            }
+            Self::BufferJson(path) => {
+                let account = read_buffer_json(path)?;
+                verify_buffer_data(&account, program)
+            }
+        }
+    }
+}
+
+fn verify_buffer_data(
+    account: &Account,
+    program: &Program,
+) -> Result<(Vec<u8>, Vec<u8>), CbmtError> {
+    let elf = verify_buffer_account(account, program).map_err(CbmtError::Validation)?;
+    let raw = account.data[UpgradeableLoaderState::size_of_buffer_metadata()..].to_vec();
+    Ok((elf, raw))
+}
+
+/// The output of `solana account --output json`.
+#[derive(Deserialize)]
+struct BufferJson {
+    account: UiAccount,
+}
+
+async fn clone_buffer_account(
+    cluster: &Cluster,
+    rpc: &RpcArgs,
+    program: &Program,
+) -> Result<Account, CbmtError> {
+    let rpc_client = rpc.client(cluster);
+    let buffer_address = program.buffer_address_on(cluster);
+    rpc.with_retries(|| {
+        rpc_client.get_account_with_commitment(&buffer_address, rpc_client.commitment())
+    })
+    .await
+    .map_err(|err| {
+        CbmtError::Rpc(format!(
+            "Failed to fetch buffer account {}: {}",
+            buffer_address, err
+        ))
+    })?
+    .value
+    .ok_or_else(|| {
+        CbmtError::Rpc(format!(
+            "Buffer account {} not found on {}",
+            buffer_address, cluster
+        ))
+    })
+}
+
+fn read_buffer_json(path: &Path) -> Result<Account, CbmtError> {
+    let read_error = |err: &dyn std::fmt::Display| {
+        CbmtError::Harness(format!(
+            "Failed to read buffer account JSON {}: {}",
+            path.display(),
+            err
+        ))
+    };
+    let file = std::fs::File::open(path).map_err(|err| read_error(&err))?;
+    let buffer_json: BufferJson = serde_json::from_reader(file).map_err(|err| read_error(&err))?;
+    buffer_json
+        .account
+        .decode()
+        .ok_or_else(|| read_error(&"failed to decode account data"))
+}
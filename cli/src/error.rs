@@ -0,0 +1,109 @@
+//! Errors, and the child processes that can cause them.
+
+use {
+    crate::report::Status,
+    std::{
+        collections::VecDeque,
+        io::{BufRead, BufReader},
+        process::{Command, Stdio},
+    },
+    thiserror::Error,
+};
+
+/// The number of lines of a failed child process's error output kept in the
+/// error.
+const STDERR_TAIL_LINES: usize = 20;
+
+#[derive(Debug, Error)]
+pub enum CbmtError {
+    /// A request to a cluster failed.
+    #[error("RPC request failed: {0}")]
+    Rpc(String),
+    /// A program or conformance target failed to build.
+    #[error("Build failed: {0}")]
+    Build(String),
+    /// The test harness could not be set up or run.
+    #[error("Harness failed: {0}")]
+    Harness(String),
+    /// The program, registry, flags or a skip list are misconfigured.
+    #[error("Invalid configuration: {0}")]
+    Config(String),
+    /// The program failed its fixtures.
+    #[error("Fixtures failed: {0}")]
+    Fixture(String),
+    /// The program failed a check.
+    #[error("Validation failed: {0}")]
+    Validation(String),
+}
+
+impl CbmtError {
+    /// The status of a run that ended with this error.
+    pub fn status(&self) -> Status {
+        match self {
+            Self::Fixture(_) | Self::Validation(_) => Status::Failed,
+            Self::Rpc(_) | Self::Build(_) | Self::Harness(_) | Self::Config(_) => Status::Errored,
+        }
+    }
+}
+
+/// Run a child process to completion. Its error output is shown as it runs,
+/// and the tail of it is included in the error, made with `kind`, if it
+/// fails.
+pub fn run_command(command: &mut Command, kind: fn(String) -> CbmtError) -> Result<(), CbmtError> {
+    let mut child = command
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| kind(format!("Failed to run {}: {}", describe(command), err)))?;
+
+    let mut tail = VecDeque::with_capacity(STDERR_TAIL_LINES);
+    let stderr = child.stderr.take().expect("Error output is piped");
+    for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+        eprintln!("{}", line);
+        if tail.len() == STDERR_TAIL_LINES {
+            tail.pop_front();
+        }
+        tail.push_back(line);
+    }
+
+    let status = child
+        .wait()
+        .map_err(|err| kind(format!("Failed to wait for {}: {}", describe(command), err)))?;
+    if !status.success() {
+        return Err(kind(failure(command, status, Vec::from(tail).join("\n"))));
+    }
+    Ok(())
+}
+
+/// Run a child process to completion, capturing its output. Returns its
+/// trimmed standard output, or an error made with `kind`, including its error
+/// output, if it fails.
+pub fn command_output(
+    command: &mut Command,
+    kind: fn(String) -> CbmtError,
+) -> Result<String, CbmtError> {
+    let output = command
+        .output()
+        .map_err(|err| kind(format!("Failed to run {}: {}", describe(command), err)))?;
+    if !output.status.success() {
+        return Err(kind(failure(
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn failure(command: &Command, status: std::process::ExitStatus, stderr: String) -> String {
+    if stderr.is_empty() {
+        format!("{} exited with {}", describe(command), status)
+    } else {
+        format!("{} exited with {}:\n{}", describe(command), status, stderr)
+    }
+}
+
+fn describe(command: &Command) -> String {
+    let mut words = vec![command.get_program().to_string_lossy()];
+    words.extend(command.get_args().map(|arg| arg.to_string_lossy()));
+    format!("`{}`", words.join(" "))
+}
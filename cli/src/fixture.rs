@@ -0,0 +1,533 @@
+//! Native fixture runner.
+//!
+//! Decodes Firedancer's `InstrFixture` protobufs and executes their inputs
+//! against conformance targets built from solfuzz-agave, loaded in-process
+//! through their `sol_compat_instr_execute_v1` entrypoint. The returned
+//! effects are compared either to the fixture's expected effects, or to the
+//! effects returned by another target.
+
+use {
+    crate::{
+        report::{self, Status},
+        skip::FixtureFilter,
+    },
+    libloading::{Library, Symbol},
+    prost::Message,
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        cell::RefCell,
+        ffi::c_int,
+        fmt::Display,
+        path::{Path, PathBuf},
+        time::{Duration, Instant},
+    },
+};
+
+/// The size of the buffer targets write their effects to.
+const OUTPUT_BUFFER_SIZE: usize = 100 * 1024 * 1024;
+
+/// The most failed fixtures named in the error ending a run.
+const MAX_REPORTED_FIXTURE_NAMES: usize = 10;
+
+/// The log level passed to a target on initialization.
+const TARGET_LOG_LEVEL: c_int = 5;
+
+type InitFn = unsafe extern "C" fn(c_int);
+type FiniFn = unsafe extern "C" fn();
+type ExecuteFn = unsafe extern "C" fn(*mut u8, *mut u64, *mut u8, u64) -> c_int;
+
+/// `org.solana.sealevel.v1.InstrFixture`.
+///
+/// The input is kept as raw bytes, rather than a decoded `InstrContext`, so
+/// it reaches the target exactly as written. Embedded messages and bytes
+/// share the same wire encoding.
+#[derive(Clone, PartialEq, Message)]
+pub struct InstrFixture {
+    #[prost(bytes = "vec", tag = "2")]
+    pub input: Vec<u8>,
+    #[prost(message, optional, tag = "3")]
+    pub output: Option<InstrEffects>,
+}
+
+/// `org.solana.sealevel.v1.InstrEffects`.
+#[derive(Clone, PartialEq, Message)]
+pub struct InstrEffects {
+    #[prost(int32, tag = "1")]
+    pub result: i32,
+    #[prost(uint32, tag = "2")]
+    pub custom_err: u32,
+    #[prost(message, repeated, tag = "3")]
+    pub modified_accounts: Vec<AcctState>,
+    #[prost(uint64, tag = "4")]
+    pub cu_avail: u64,
+    #[prost(bytes = "vec", tag = "5")]
+    pub return_data: Vec<u8>,
+}
+
+/// `org.solana.sealevel.v1.AcctState`.
+#[derive(Clone, PartialEq, Message)]
+pub struct AcctState {
+    #[prost(bytes = "vec", tag = "1")]
+    pub address: Vec<u8>,
+    #[prost(uint64, tag = "2")]
+    pub lamports: u64,
+    #[prost(bytes = "vec", tag = "3")]
+    pub data: Vec<u8>,
+    #[prost(bool, tag = "4")]
+    pub executable: bool,
+    #[prost(uint64, tag = "5")]
+    pub rent_epoch: u64,
+    #[prost(bytes = "vec", tag = "6")]
+    pub owner: Vec<u8>,
+}
+
+/// A conformance target, loaded in-process.
+pub struct Target {
+    library: Library,
+    /// The buffer the target writes effects to, allocated once and reused
+    /// for every input.
+    output: RefCell<Vec<u8>>,
+}
+
+impl Target {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let library = unsafe { Library::new(path) }
+            .map_err(|err| format!("Failed to load target {}: {}", path.display(), err))?;
+        unsafe {
+            library
+                .get::<ExecuteFn>(b"sol_compat_instr_execute_v1\0")
+                .map_err(|err| format!("Target {} is not executable: {}", path.display(), err))?;
+            if let Ok(init) = library.get::<InitFn>(b"sol_compat_init\0") {
+                init(TARGET_LOG_LEVEL);
+            }
+        }
+        Ok(Self {
+            library,
+            output: RefCell::new(vec![0; OUTPUT_BUFFER_SIZE]),
+        })
+    }
+
+    /// Execute an encoded `InstrContext`, returning the effects.
+    pub fn execute(&self, input: &[u8]) -> Result<InstrEffects, String> {
+        let mut input = input.to_vec();
+        let mut output = self.output.borrow_mut();
+        let mut output_size = output.len() as u64;
+
+        let status = unsafe {
+            let execute: Symbol<ExecuteFn> = self
+                .library
+                .get(b"sol_compat_instr_execute_v1\0")
+                .expect("Entrypoint was checked on load");
+            execute(
+                output.as_mut_ptr(),
+                &mut output_size,
+                input.as_mut_ptr(),
+                input.len() as u64,
+            )
+        };
+        if status == 0 {
+            return Err("Target failed to execute the input".to_string());
+        }
+
+        let effects = output
+            .get(..output_size as usize)
+            .ok_or_else(|| "Target reported more effects than fit its buffer".to_string())?;
+        InstrEffects::decode(effects).map_err(|err| format!("Failed to decode effects: {}", err))
+    }
+}
+
+impl Drop for Target {
+    fn drop(&mut self) {
+        unsafe {
+            if let Ok(fini) = self.library.get::<FiniFn>(b"sol_compat_fini\0") {
+                fini();
+            }
+        }
+    }
+}
+
+/// The outcome of running a single fixture.
+pub enum FixtureOutcome {
+    Passed,
+    /// The effects differed, with each difference described.
+    Failed(Vec<String>),
+    /// The fixture could not be run.
+    Errored(String),
+    /// The fixture was filtered out, for the reason given.
+    Skipped(String),
+}
+
+pub struct FixtureResult {
+    pub name: String,
+    pub outcome: FixtureOutcome,
+    pub duration: Duration,
+}
+
+/// Run every fixture against the target, comparing the effects to the
+/// fixture's expected effects.
+pub fn run_fixtures(
+    fixtures_path: &Path,
+    filter: &FixtureFilter,
+    target: &Target,
+) -> Result<Vec<FixtureResult>, String> {
+    run_each(fixtures_path, filter, |fixture| {
+        let expected = fixture
+            .output
+            .as_ref()
+            .ok_or("Fixture has no expected effects")?;
+        let actual = target.execute(&fixture.input)?;
+        Ok(compare_effects(expected, &actual))
+    })
+}
+
+/// Run every fixture's input against both targets, comparing their effects.
+pub fn run_conformance(
+    fixtures_path: &Path,
+    filter: &FixtureFilter,
+    reference: &Target,
+    target: &Target,
+) -> Result<Vec<FixtureResult>, String> {
+    run_each(fixtures_path, filter, |fixture| {
+        let expected = reference.execute(&fixture.input)?;
+        let actual = target.execute(&fixture.input)?;
+        Ok(compare_effects(&expected, &actual))
+    })
+}
+
+/// Print every failed or skipped fixture and a summary, and record every
+/// fixture. Returns an error counting and naming the fixtures that failed or
+/// errored, if any did.
+pub fn report_results(results: &[FixtureResult]) -> Result<(), String> {
+    let mut failed = 0;
+    let mut errored = 0;
+    let mut unsuccessful = Vec::new();
+    let mut skipped = Vec::new();
+
+    println!();
+    for result in results {
+        let (status, diff) = match &result.outcome {
+            FixtureOutcome::Passed => (Status::Passed, None),
+            FixtureOutcome::Failed(differences) => {
+                failed += 1;
+                unsuccessful.push(result.name.as_str());
+                println!("      {} FAILED", result.name);
+                for difference in differences {
+                    println!("        {}", difference);
+                }
+                (Status::Failed, Some(differences.join("\n")))
+            }
+            FixtureOutcome::Errored(err) => {
+                errored += 1;
+                unsuccessful.push(result.name.as_str());
+                println!("      {} ERRORED ({})", result.name, err);
+                (Status::Errored, Some(err.clone()))
+            }
+            FixtureOutcome::Skipped(reason) => {
+                skipped.push((&result.name, reason));
+                (Status::Skipped, Some(reason.clone()))
+            }
+        };
+        report::record_case(&result.name, status, diff, result.duration);
+    }
+
+    if !skipped.is_empty() {
+        println!();
+        for (name, reason) in &skipped {
+            println!("      {} SKIPPED ({})", name, reason);
+        }
+    }
+
+    println!();
+    println!(
+        "    Passed        : {}",
+        results.len() - failed - errored - skipped.len()
+    );
+    println!("    Failed        : {}", failed);
+    println!("    Errored       : {}", errored);
+    println!("    Skipped       : {}", skipped.len());
+    println!();
+
+    if unsuccessful.is_empty() {
+        return Ok(());
+    }
+    let mut names = unsuccessful
+        .iter()
+        .take(MAX_REPORTED_FIXTURE_NAMES)
+        .copied()
+        .collect::<Vec<_>>()
+        .join(", ");
+    if unsuccessful.len() > MAX_REPORTED_FIXTURE_NAMES {
+        names.push_str(&format!(
+            " and {} more",
+            unsuccessful.len() - MAX_REPORTED_FIXTURE_NAMES
+        ));
+    }
+    Err(format!(
+        "{} failed and {} errored of {} fixtures: {}",
+        failed,
+        errored,
+        results.len(),
+        names
+    ))
+}
+
+fn run_each<F>(
+    fixtures_path: &Path,
+    filter: &FixtureFilter,
+    run: F,
+) -> Result<Vec<FixtureResult>, String>
+where
+    F: Fn(&InstrFixture) -> Result<FixtureOutcome, String>,
+{
+    Ok(find_fixtures(fixtures_path)?
+        .into_iter()
+        .map(|path| {
+            let start = Instant::now();
+            let name = path.strip_prefix(fixtures_path).unwrap_or(&path);
+            let outcome = match filter.skip_reason(name) {
+                Some(reason) => FixtureOutcome::Skipped(reason),
+                None => std::fs::read(&path)
+                    .map_err(|err| format!("Failed to read fixture: {}", err))
+                    .and_then(|bytes| {
+                        InstrFixture::decode(bytes.as_slice())
+                            .map_err(|err| format!("Failed to decode fixture: {}", err))
+                    })
+                    .and_then(|fixture| run(&fixture))
+                    .unwrap_or_else(FixtureOutcome::Errored),
+            };
+            FixtureResult {
+                name: name.display().to_string(),
+                outcome,
+                duration: start.elapsed(),
+            }
+        })
+        .collect())
+}
+
+/// Find every `.fix` file under the path, in a stable order.
+fn find_fixtures(path: &Path) -> Result<Vec<PathBuf>, String> {
+    let read_error = |directory: &Path, err: std::io::Error| {
+        format!(
+            "Failed to read fixtures directory {}: {}",
+            directory.display(),
+            err
+        )
+    };
+
+    let mut fixtures = Vec::new();
+    let mut directories = vec![path.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let entries = std::fs::read_dir(&directory).map_err(|err| read_error(&directory, err))?;
+        for entry in entries {
+            let path = entry.map_err(|err| read_error(&directory, err))?.path();
+            if path.is_dir() {
+                directories.push(path);
+            } else if path.extension().is_some_and(|extension| extension == "fix") {
+                fixtures.push(path);
+            }
+        }
+    }
+    fixtures.sort();
+    Ok(fixtures)
+}
+
+/// Describe every difference between two sets of effects. Compute units
+/// differ between a builtin and its BPF version, so are not compared.
+fn compare_effects(expected: &InstrEffects, actual: &InstrEffects) -> FixtureOutcome {
+    let mut differences = Vec::new();
+
+    compare(&mut differences, "result", expected.result, actual.result);
+    compare(
+        &mut differences,
+        "custom error",
+        expected.custom_err,
+        actual.custom_err,
+    );
+    compare(
+        &mut differences,
+        "return data",
+        hex(&expected.return_data),
+        hex(&actual.return_data),
+    );
+
+    let mut expected_accounts = expected.modified_accounts.iter().collect::<Vec<_>>();
+    let mut actual_accounts = actual.modified_accounts.iter().collect::<Vec<_>>();
+    expected_accounts.sort_by(|a, b| a.address.cmp(&b.address));
+    actual_accounts.sort_by(|a, b| a.address.cmp(&b.address));
+
+    compare(
+        &mut differences,
+        "modified accounts",
+        addresses(&expected_accounts),
+        addresses(&actual_accounts),
+    );
+    for expected in &expected_accounts {
+        let Some(actual) = actual_accounts
+            .iter()
+            .find(|actual| actual.address == expected.address)
+        else {
+            continue;
+        };
+        let account_address = address(&expected.address);
+        compare(
+            &mut differences,
+            &format!("{} lamports", account_address),
+            expected.lamports,
+            actual.lamports,
+        );
+        compare(
+            &mut differences,
+            &format!("{} owner", account_address),
+            address(&expected.owner),
+            address(&actual.owner),
+        );
+        compare(
+            &mut differences,
+            &format!("{} executable", account_address),
+            expected.executable,
+            actual.executable,
+        );
+        if expected.data != actual.data {
+            let offset = expected
+                .data
+                .iter()
+                .zip(&actual.data)
+                .position(|(a, b)| a != b)
+                .unwrap_or(expected.data.len().min(actual.data.len()));
+            differences.push(format!(
+                "{} data: differs at byte {} (expected {} bytes, got {})",
+                account_address,
+                offset,
+                expected.data.len(),
+                actual.data.len()
+            ));
+        }
+    }
+
+    if differences.is_empty() {
+        FixtureOutcome::Passed
+    } else {
+        FixtureOutcome::Failed(differences)
+    }
+}
+
+fn compare(
+    differences: &mut Vec<String>,
+    label: &str,
+    expected: impl Display,
+    actual: impl Display,
+) {
+    let (expected, actual) = (expected.to_string(), actual.to_string());
+    if expected != actual {
+        differences.push(format!("{}: expected {}, got {}", label, expected, actual));
+    }
+}
+
+fn address(bytes: &[u8]) -> String {
+    Pubkey::try_from(bytes).map_or_else(|_| hex(bytes), |pubkey| pubkey.to_string())
+}
+
+fn addresses(accounts: &[&AcctState]) -> String {
+    let addresses = accounts
+        .iter()
+        .map(|account| address(&account.address))
+        .collect::<Vec<_>>();
+    format!("[{}]", addresses.join(", "))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return "(empty)".to_string();
+    }
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(address: Pubkey, lamports: u64) -> AcctState {
+        AcctState {
+            address: address.to_bytes().to_vec(),
+            lamports,
+            data: vec![1, 2, 3],
+            executable: false,
+            rent_epoch: 0,
+            owner: Pubkey::new_unique().to_bytes().to_vec(),
+        }
+    }
+
+    /// Encode a fixture expecting the effects, and decode its expected
+    /// effects again, as the runner would.
+    fn round_trip(expected: InstrEffects) -> InstrEffects {
+        let fixture = InstrFixture {
+            input: vec![0xab; 4],
+            output: Some(expected),
+        };
+        let decoded = InstrFixture::decode(fixture.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded, fixture);
+        decoded.output.unwrap()
+    }
+
+    #[test]
+    fn test_compare_effects_passed() {
+        let effects = InstrEffects {
+            modified_accounts: vec![account(Pubkey::new_unique(), 10)],
+            return_data: vec![7],
+            ..InstrEffects::default()
+        };
+        let expected = round_trip(effects.clone());
+
+        assert!(matches!(
+            compare_effects(&expected, &effects),
+            FixtureOutcome::Passed
+        ));
+    }
+
+    #[test]
+    fn test_compare_effects_failed() {
+        let address = Pubkey::new_unique();
+        let expected = round_trip(InstrEffects {
+            modified_accounts: vec![account(address, 10)],
+            ..InstrEffects::default()
+        });
+        let mut actual = expected.clone();
+        actual.result = 1;
+        actual.modified_accounts[0].lamports = 11;
+
+        let FixtureOutcome::Failed(differences) = compare_effects(&expected, &actual) else {
+            panic!("Differing effects passed");
+        };
+        assert_eq!(
+            differences,
+            vec![
+                "result: expected 0, got 1".to_string(),
+                format!("{} lamports: expected 10, got 11", address),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compare_effects_missing_account() {
+        let present = account(Pubkey::new_unique(), 10);
+        let missing = account(Pubkey::new_unique(), 20);
+        let expected = round_trip(InstrEffects {
+            modified_accounts: vec![present.clone(), missing],
+            ..InstrEffects::default()
+        });
+        let actual = InstrEffects {
+            modified_accounts: vec![present],
+            ..InstrEffects::default()
+        };
+
+        let FixtureOutcome::Failed(differences) = compare_effects(&expected, &actual) else {
+            panic!("Effects missing an account passed");
+        };
+        assert_eq!(differences.len(), 1);
+        assert!(
+            differences[0].starts_with("modified accounts:"),
+            "{}",
+            differences[0]
+        );
+    }
+}
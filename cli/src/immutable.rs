@@ -0,0 +1,133 @@
+//! Immutability of migrated programs.
+//!
+//! A migrated program has no upgrade authority, so nobody should be able to
+//! upgrade, reassign or close it. Each attempt is signed by several keypairs,
+//! including one holding a valid upgrade buffer, so an upgrade can only be
+//! refused because the program itself is immutable.
+
+use {
+    crate::{
+        report::ensure,
+        state::check_account_unchanged,
+        validator::{report_checks, ValidatorContext},
+    },
+    solana_sdk::{
+        bpf_loader_upgradeable::{self, get_program_data_address},
+        pubkey::Pubkey,
+        signature::Keypair,
+        signer::Signer,
+    },
+};
+
+impl ValidatorContext {
+    /// Attempt to upgrade, set the authority of and close the program, with
+    /// several signers, and ensure every attempt fails.
+    pub async fn assert_program_is_immutable(&self, program_id: &Pubkey, buffer_address: &Pubkey) {
+        let programdata_address = get_program_data_address(program_id);
+        let upgrade_buffer = self
+            .upgrade_buffers
+            .get(buffer_address)
+            .expect("No upgrade buffer staged");
+
+        let program_before = self.get_account(program_id).await.unwrap();
+        let programdata_before = self.get_account(&programdata_address).await.unwrap();
+
+        let new_keypair = Keypair::new();
+        let mut buffer_authority = &self.upgrade_authority;
+        let mut checks = Vec::new();
+
+        for (signer_name, authority) in [
+            ("upgrade buffer authority", &self.upgrade_authority),
+            ("payer", &self.payer),
+            ("new keypair", &new_keypair),
+        ] {
+            // Hand the upgrade buffer to the signer first.
+            if authority.pubkey() != buffer_authority.pubkey() {
+                self.send_transaction(
+                    &[bpf_loader_upgradeable::set_buffer_authority(
+                        upgrade_buffer,
+                        &buffer_authority.pubkey(),
+                        &authority.pubkey(),
+                    )],
+                    &self.payer.pubkey(),
+                    &signers(&self.payer, buffer_authority),
+                )
+                .await;
+                buffer_authority = authority;
+            }
+
+            for (instruction_name, instruction) in [
+                (
+                    "Upgrade",
+                    bpf_loader_upgradeable::upgrade(
+                        program_id,
+                        upgrade_buffer,
+                        &authority.pubkey(),
+                        &self.payer.pubkey(),
+                    ),
+                ),
+                (
+                    "SetAuthority",
+                    bpf_loader_upgradeable::set_upgrade_authority(
+                        program_id,
+                        &authority.pubkey(),
+                        Some(&authority.pubkey()),
+                    ),
+                ),
+                (
+                    "Close",
+                    bpf_loader_upgradeable::close_any(
+                        &programdata_address,
+                        &self.payer.pubkey(),
+                        Some(&authority.pubkey()),
+                        Some(program_id),
+                    ),
+                ),
+            ] {
+                let result = self
+                    .try_send_transaction(
+                        &[instruction],
+                        &self.payer.pubkey(),
+                        &signers(&self.payer, authority),
+                    )
+                    .await;
+                checks.push((
+                    format!("{} signed by {} fails", instruction_name, signer_name),
+                    match result {
+                        Ok(signature) => Err(format!("succeeded: {}", signature)),
+                        Err(_) => Ok(()),
+                    },
+                ));
+            }
+        }
+
+        for (label, address, before) in [
+            ("Program account unchanged", program_id, program_before),
+            (
+                "ProgramData account unchanged",
+                &programdata_address,
+                programdata_before,
+            ),
+        ] {
+            let after = self.get_account(address).await;
+            checks.push((
+                label.to_string(),
+                match after {
+                    Some(after) => check_account_unchanged(&before, &after),
+                    None => Err("account was closed".to_string()),
+                },
+            ));
+        }
+
+        ensure!(report_checks(&checks), "Migrated program can be modified");
+    }
+}
+
+/// The payer and the authority, without signing twice if they're the same.
+fn signers<'a>(payer: &'a Keypair, authority: &'a Keypair) -> Vec<&'a Keypair> {
+    if authority.pubkey() == payer.pubkey() {
+        vec![payer]
+    } else {
+        vec![payer, authority]
+    }
+}
@@ -0,0 +1,199 @@
+//! ELF static analysis.
+//!
+//! Reports the properties of a program ELF relevant to a migration, and
+//! checks every syscall it imports is registered by the linked Agave runtime.
+//! A buffer referencing an unregistered syscall would fail to deploy at the
+//! epoch boundary.
+
+use {
+    goblin::elf::{section_header::SHN_UNDEF, Elf},
+    solana_bpf_loader_program::syscalls::create_program_runtime_environment_v1,
+    solana_compute_budget::compute_budget::ComputeBudget,
+    solana_program_runtime::invoke_context::InvokeContext,
+    solana_rbpf::{
+        ebpf::EF_SBPF_V2, elf::Executable, program::BuiltinProgram, static_analysis::Analysis,
+    },
+    solana_sdk::feature_set::FeatureSet,
+    std::{collections::BTreeSet, sync::Arc},
+};
+
+const EM_BPF: u16 = 247;
+const EM_SBPF: u16 = 263;
+
+const R_BPF_64_64: u32 = 1;
+const R_BPF_64_RELATIVE: u32 = 8;
+const R_BPF_64_32: u32 = 10;
+
+/// Static analysis report for a program ELF.
+pub struct ElfReport {
+    pub machine: String,
+    pub sbpf_version: String,
+    pub entrypoint: Option<String>,
+    pub sections: Vec<(String, u64)>,
+    pub relocations: Vec<(String, usize)>,
+    pub syscalls: Vec<(String, bool)>,
+    pub load_result: Result<(), String>,
+}
+
+impl ElfReport {
+    /// Whether the ELF would deploy against the linked runtime.
+    pub fn is_deployable(&self) -> bool {
+        self.load_result.is_ok() && self.syscalls.iter().all(|(_, registered)| *registered)
+    }
+
+    pub fn print(&self) {
+        println!();
+        println!("    Machine       : {}", self.machine);
+        println!("    sBPF Version  : {}", self.sbpf_version);
+        println!(
+            "    Entrypoint    : {}",
+            self.entrypoint.as_deref().unwrap_or("<not found>")
+        );
+        println!();
+        println!("    Sections:");
+        for (name, size) in &self.sections {
+            println!("      {:<24} {:>10} bytes", name, size);
+        }
+        println!();
+        println!("    Relocations:");
+        for (kind, count) in &self.relocations {
+            println!("      {:<24} {:>10}", kind, count);
+        }
+        println!();
+        println!("    Syscalls:");
+        for (name, registered) in &self.syscalls {
+            let status = if *registered {
+                "registered"
+            } else {
+                "NOT REGISTERED"
+            };
+            println!("      {:<40} {}", name, status);
+        }
+        println!();
+        match &self.load_result {
+            Ok(()) => println!("    Runtime Load  : ok"),
+            Err(err) => println!("    Runtime Load  : failed ({})", err),
+        }
+        println!();
+    }
+}
+
+/// Create the program runtime environment used to deploy a migrated program,
+/// with every feature enabled.
+fn runtime_environment() -> BuiltinProgram<InvokeContext<'static>> {
+    create_program_runtime_environment_v1(
+        &FeatureSet::all_enabled(),
+        &ComputeBudget::default(),
+        /* reject_deployment_of_broken_elfs */ true,
+        /* debugging_features */ false,
+    )
+    .expect("Failed to create program runtime environment")
+}
+
+/// Analyze a program ELF.
+pub fn inspect_elf(elf: &[u8]) -> Result<ElfReport, String> {
+    let parsed = Elf::parse(elf).map_err(|err| format!("Failed to parse ELF: {}", err))?;
+    let header = &parsed.header;
+
+    let machine = match header.e_machine {
+        EM_BPF => "BPF (247)".to_string(),
+        EM_SBPF => "SBF (263)".to_string(),
+        other => format!("Unknown ({})", other),
+    };
+
+    let sbpf_version = if header.e_flags == EF_SBPF_V2 {
+        "v2".to_string()
+    } else {
+        "v1".to_string()
+    };
+
+    let entrypoint = parsed
+        .dynsyms
+        .iter()
+        .map(|sym| (sym, &parsed.dynstrtab))
+        .chain(parsed.syms.iter().map(|sym| (sym, &parsed.strtab)))
+        .find(|(sym, _)| sym.st_value == header.e_entry && sym.st_shndx != SHN_UNDEF as usize)
+        .and_then(|(sym, strtab)| strtab.get_at(sym.st_name))
+        .map(str::to_string);
+
+    let sections = parsed
+        .section_headers
+        .iter()
+        .filter(|section| section.sh_size > 0)
+        .map(|section| {
+            let name = parsed
+                .shdr_strtab
+                .get_at(section.sh_name)
+                .unwrap_or("<unnamed>")
+                .to_string();
+            (name, section.sh_size)
+        })
+        .collect();
+
+    let mut relocation_counts = [0usize; 4];
+    let mut imports = BTreeSet::new();
+    for reloc in parsed.dynrels.iter() {
+        match reloc.r_type {
+            R_BPF_64_64 => relocation_counts[0] += 1,
+            R_BPF_64_RELATIVE => relocation_counts[1] += 1,
+            R_BPF_64_32 => {
+                relocation_counts[2] += 1;
+                // Calls to undefined symbols are syscalls.
+                if let Some(sym) = parsed.dynsyms.get(reloc.r_sym) {
+                    if sym.st_shndx == SHN_UNDEF as usize {
+                        if let Some(name) = parsed.dynstrtab.get_at(sym.st_name) {
+                            imports.insert(name.to_string());
+                        }
+                    }
+                }
+            }
+            _ => relocation_counts[3] += 1,
+        }
+    }
+    let relocations = vec![
+        ("R_BPF_64_64".to_string(), relocation_counts[0]),
+        ("R_BPF_64_RELATIVE".to_string(), relocation_counts[1]),
+        ("R_BPF_64_32".to_string(), relocation_counts[2]),
+        ("Other".to_string(), relocation_counts[3]),
+    ];
+
+    let loader = Arc::new(runtime_environment());
+
+    let syscalls = imports
+        .into_iter()
+        .map(|name| {
+            let registered = loader
+                .get_function_registry()
+                .lookup_by_name(name.as_bytes())
+                .is_some();
+            (name, registered)
+        })
+        .collect();
+
+    let load_result = Executable::<InvokeContext>::load(elf, loader)
+        .map(|_| ())
+        .map_err(|err| err.to_string());
+
+    Ok(ElfReport {
+        machine,
+        sbpf_version,
+        entrypoint,
+        sections,
+        relocations,
+        syscalls,
+        load_result,
+    })
+}
+
+/// Disassemble a program ELF, as loaded by the linked runtime.
+pub fn disassemble(elf: &[u8]) -> Result<String, String> {
+    let executable = Executable::<InvokeContext>::load(elf, Arc::new(runtime_environment()))
+        .map_err(|err| format!("Failed to load ELF: {}", err))?;
+    let analysis = Analysis::from_executable(&executable)
+        .map_err(|err| format!("Failed to analyze ELF: {}", err))?;
+    let mut output = Vec::new();
+    analysis
+        .disassemble(&mut output)
+        .map_err(|err| format!("Failed to disassemble ELF: {}", err))?;
+    Ok(String::from_utf8_lossy(&output).to_string())
+}
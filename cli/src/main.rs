@@ -1,29 +1,65 @@
 //! CLI to test Core BPF program migration on feature activations.
 
+mod accounting;
 mod cluster;
 mod conformance;
+mod cu;
+mod deps;
+mod diff;
+mod elf;
+mod error;
 mod file;
+mod fixture;
+mod immutable;
+mod inspect;
 mod output;
 mod program;
+mod registry;
+mod report;
+mod reproduce;
+mod skip;
+mod smoke;
+mod state;
+mod stub;
+mod timing;
 mod validator;
+mod verify;
 
 use {
     crate::{
-        cluster::Cluster,
+        cluster::{Cluster, RpcArgs},
         conformance::ConformanceHandler,
-        output::{output, title_conformance_test, title_fixtures_test, title_stub_test},
-        program::Program,
-        validator::{MigrationTarget, ValidatorContext},
+        cu::{run_cu_comparison, CuThresholds},
+        deps::{fetch_deps, CacheArgs, DependencyCache},
+        diff::{diff_elfs, diff_raw},
+        elf::{ElfSource, ElfSourceArgs},
+        error::CbmtError,
+        fixture::report_results,
+        inspect::inspect_elf,
+        output::{
+            output, title_conformance_test, title_diff, title_fixtures_test, title_inspect,
+            title_verify_build,
+        },
+        program::CustomProgramArgs,
+        registry::Registry,
+        report::{AssertionFailure, ReportFormat, Status},
+        reproduce::{checkout_commit, compare_build, verifiable_build},
+        skip::{FixtureFilter, FixtureFilterArgs},
+        stub::{run_stub_test, StubOptions},
+        validator::{FailureMode, ValidatorArgs},
     },
     clap::{Parser, Subcommand},
-    solana_rpc_client::nonblocking::rpc_client::RpcClient,
-    solana_sdk::bpf_loader_upgradeable::UpgradeableLoaderState,
-    std::{fs::File, io::Write, path::Path, process::Command},
+    futures::FutureExt,
+    std::{
+        fs::File,
+        io::Write,
+        panic::AssertUnwindSafe,
+        path::{Path, PathBuf},
+        process::ExitCode,
+    },
 };
 
 const ELF_DIRECTORY: &str = "elfs";
-const MANIFEST_PATH_ACTIVATOR: &str = "./programs/activator/Cargo.toml";
-const MANIFEST_PATH_STUB: &str = "./programs/stub/Cargo.toml";
 
 #[derive(Subcommand)]
 enum SubCommand {
@@ -35,29 +71,95 @@ enum SubCommand {
     ///
     /// The stub program has a deterministic processor, so the test suite in
     /// the program's crate can be used to ensure the migration was successful.
+    ///
+    /// Several programs can be migrated in the same test validator.
     Stub {
-        /// The program to test.
-        program: Program,
-        /// Slots per epoch (defaults to 50).
-        #[arg(short, long, default_value = "50")]
-        slots_per_epoch: u64,
+        /// The programs to test, by their names in the program registry, or
+        /// `custom` to describe a migration target with flags.
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        programs: Vec<String>,
+        /// Test every registered program.
+        #[arg(long)]
+        all: bool,
+        /// Activate each feature in its own epoch, rather than all in the
+        /// same epoch.
+        #[arg(long)]
+        stagger: bool,
+        /// Populate the buffers with the real program ELFs, loaded from the
+        /// ELF source, and run each program's smoke suite instead of the
+        /// stub tests.
+        #[arg(long)]
+        real_elf: bool,
+        /// Create accounts through each builtin before migration, and check
+        /// they are unchanged and still usable after migration. Requires
+        /// `--real-elf`.
+        #[arg(long, requires = "real_elf")]
+        preserve_state: bool,
+        /// Stage a malformed buffer and check the migration is skipped. May
+        /// be repeated, each failure mode running in a fresh test validator.
+        #[arg(long, value_enum, conflicts_with_all = ["stagger", "preserve_state"])]
+        failure_mode: Vec<FailureMode>,
+        /// Check the change in total supply across each migration is
+        /// accounted for by the lamports burned and funded.
+        #[arg(long, conflicts_with = "failure_mode")]
+        accounting: bool,
+        /// Invoke the programs continuously across the epoch boundary, and
+        /// check each is only reachable as a BPF program from the slot after
+        /// its migration.
+        #[arg(long, conflicts_with_all = ["real_elf", "stagger", "failure_mode"])]
+        timing_matrix: bool,
+        /// Activate the features and revoke them before the epoch boundary,
+        /// checking nothing is migrated, before activating them again.
+        #[arg(long, conflicts_with = "failure_mode")]
+        revoke_first: bool,
+        #[command(flatten)]
+        custom: CustomProgramArgs,
+        #[command(flatten)]
+        source: ElfSourceArgs,
+        #[command(flatten)]
+        validator: ValidatorArgs,
+    },
+    /// Compare the compute units consumed by a builtin and its BPF version.
+    ///
+    /// Runs the program's smoke suite in a test validator before and after
+    /// migrating it with the real program ELF, and reports the compute units
+    /// consumed by each step. Steps exceeding the thresholds are flagged.
+    Cu {
+        /// The program to compare, by its name in the program registry, or
+        /// `custom` to describe the migration target with flags.
+        program: String,
+        #[command(flatten)]
+        custom: CustomProgramArgs,
+        #[command(flatten)]
+        source: ElfSourceArgs,
+        #[command(flatten)]
+        thresholds: CuThresholds,
+        #[command(flatten)]
+        validator: ValidatorArgs,
     },
     /// Test a buffer account's ELF against a suite of Firedancer fixtures.
     ///
     /// Clones the ELF from the buffer account and runs the fixtures against
     /// the original builtin.
     Fixtures {
-        /// The program to test.
-        program: Program,
-        /// The cluster to clone the buffer account data from.
-        #[arg(short, long, default_value = "mainnet-beta")]
-        cluster: Cluster,
+        /// The program to test, by its name in the program registry, or
+        /// `custom` to describe the migration target with flags.
+        program: String,
+        #[command(flatten)]
+        custom: CustomProgramArgs,
+        #[command(flatten)]
+        source: ElfSourceArgs,
         /// Whether or not to use Mollusk fixtures. Uses Firedancer instead.
         #[arg(short, long, default_value = "false")]
         use_mollusk_fixtures: bool,
-        /// Whether or not to skip installing the Firedancer tool suite.
+        /// Whether or not to skip checking out the harness and fixtures,
+        /// using them as they are in the cache.
         #[arg(short, long, default_value = "false")]
         skip_setup: bool,
+        #[command(flatten)]
+        filter: FixtureFilterArgs,
+        #[command(flatten)]
+        cache: CacheArgs,
     },
     /// Test a buffer account's ELF against the original builtin using
     /// Firedancer's conformance tooling.
@@ -65,180 +167,483 @@ enum SubCommand {
     /// Clones the ELF from the buffer account and runs the conformance tests
     /// against the original builtin.
     Conformance {
-        /// The program to test.
-        program: Program,
-        /// The cluster to clone the buffer account data from.
-        #[arg(short, long, default_value = "mainnet-beta")]
-        cluster: Cluster,
+        /// The program to test, by its name in the program registry, or
+        /// `custom` to describe the migration target with flags.
+        program: String,
+        #[command(flatten)]
+        custom: CustomProgramArgs,
+        #[command(flatten)]
+        source: ElfSourceArgs,
         /// Whether or not to use Mollusk fixtures. Uses Firedancer instead.
         #[arg(short, long, default_value = "false")]
         use_mollusk_fixtures: bool,
-        /// Whether or not to skip installing the Firedancer tool suite.
+        /// Whether or not to skip checking out the harness and fixtures,
+        /// using them as they are in the cache.
         #[arg(short, long, default_value = "false")]
         skip_setup: bool,
+        #[command(flatten)]
+        filter: FixtureFilterArgs,
+        #[command(flatten)]
+        cache: CacheArgs,
+    },
+    /// Check out the fixtures and conformance harness dependencies.
+    ///
+    /// Populates the cache with every repository the fixtures and
+    /// conformance tests depend on, at the commits pinned in
+    /// `cbmt-deps.lock`, so they can be run with `--offline`.
+    FetchDeps {
+        /// Pin every dependency to the head of its branch, rewriting the
+        /// lockfile.
+        #[arg(long, conflicts_with = "offline")]
+        update: bool,
+        #[command(flatten)]
+        cache: CacheArgs,
+    },
+    /// Statically analyze a buffer account's ELF.
+    ///
+    /// Reports the ELF's machine type, sBPF version, entrypoint, sections,
+    /// relocations and imported syscalls, and checks every imported syscall
+    /// is registered by the linked Agave runtime.
+    Inspect {
+        /// The program to inspect, by its name in the program registry, or
+        /// `custom` to describe the migration target with flags.
+        program: String,
+        #[command(flatten)]
+        custom: CustomProgramArgs,
+        #[command(flatten)]
+        source: ElfSourceArgs,
     },
+    /// Compare a program's buffer ELF across two clusters, or against a
+    /// local ELF.
+    ///
+    /// Compares the ELF hashes and, if they differ, reports the differences
+    /// section by section and in the disassembly.
+    Diff {
+        /// The program to compare, by its name in the program registry, or
+        /// `custom` to describe the migration target with flags.
+        program: String,
+        #[command(flatten)]
+        custom: CustomProgramArgs,
+        /// The cluster to clone the first buffer account from.
+        #[arg(long, default_value = "devnet")]
+        from: Cluster,
+        /// Path to a local ELF file to use instead of the first buffer account.
+        #[arg(long)]
+        from_elf: Option<PathBuf>,
+        /// The cluster to clone the second buffer account from.
+        #[arg(long, default_value = "mainnet-beta")]
+        to: Cluster,
+        /// Path to a local ELF file to use instead of the second buffer account.
+        #[arg(long)]
+        to_elf: Option<PathBuf>,
+        #[command(flatten)]
+        rpc: RpcArgs,
+    },
+    /// Reproduce a buffer account's ELF from source.
+    ///
+    /// Checks out the provided commit in a local checkout of the program's
+    /// repository, runs the deterministic `solana-verify` build, and compares
+    /// the produced ELF to the buffer account's ELF. The previously checked
+    /// out branch or commit is restored afterwards.
+    VerifyBuild {
+        /// The program to verify, by its name in the program registry, or
+        /// `custom` to describe the migration target with flags.
+        program: String,
+        #[command(flatten)]
+        custom: CustomProgramArgs,
+        /// Path to a local checkout of the program's repository.
+        #[arg(long)]
+        repo_path: PathBuf,
+        /// The commit to build.
+        #[arg(long)]
+        commit: String,
+        /// The name of the library to build. Defaults to the program's
+        /// registered library name.
+        #[arg(long)]
+        library_name: Option<String>,
+        #[command(flatten)]
+        source: ElfSourceArgs,
+    },
+}
+
+impl SubCommand {
+    /// The name of the suite the command's results are reported under.
+    fn suite(&self) -> &'static str {
+        match self {
+            Self::Stub { .. } => "stub",
+            Self::Cu { .. } => "cu",
+            Self::Fixtures { .. } => "fixtures",
+            Self::Conformance { .. } => "conformance",
+            Self::FetchDeps { .. } => "fetch-deps",
+            Self::Inspect { .. } => "inspect",
+            Self::Diff { .. } => "diff",
+            Self::VerifyBuild { .. } => "verify-build",
+        }
+    }
 }
 
 #[derive(Parser)]
 struct Cli {
+    /// Path to a program registry file. Defaults to `cbmt-programs.toml` in
+    /// the working directory, if it exists.
+    #[arg(long, global = true)]
+    registry: Option<PathBuf>,
+    /// Write the results in this format. May be repeated.
+    #[arg(long, value_enum, global = true)]
+    report: Vec<ReportFormat>,
+    /// The directory to write reports to.
+    #[arg(long, global = true, default_value = ".")]
+    report_dir: PathBuf,
     #[clap(subcommand)]
     pub command: SubCommand,
 }
 
+/// Exits with 0 if every test passed, 1 if a test failed, or 2 if the
+/// harness itself failed.
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    match Cli::parse().command {
-        SubCommand::Stub {
-            program,
-            slots_per_epoch,
-        } => {
-            let program_id = program.program_id();
-            let feature_id = program.feature_gate();
-            let buffer_address = program.buffer_address();
-
-            title_stub_test(&feature_id, &buffer_address);
-
-            output("Bulding programs...");
-            cargo_build_sbf(MANIFEST_PATH_ACTIVATOR);
-            cargo_build_sbf(MANIFEST_PATH_STUB);
-
-            output("Starting test validator...");
-            let context = ValidatorContext::start(
-                &[MigrationTarget {
-                    feature_id,
-                    buffer_address,
-                    elf_name: "cbmt_program_stub",
-                }],
-                ELF_DIRECTORY,
-                slots_per_epoch,
-            )
-            .await;
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    report::begin(cli.command.suite());
+    report::install_panic_hook();
 
-            output("Checking to see if program is currently a builtin...");
-            if program_id != solana_sdk::feature::id() {
-                context.assert_program_is_builtin(&program_id).await;
+    let status = match AssertUnwindSafe(run(cli.command, cli.registry))
+        .catch_unwind()
+        .await
+    {
+        Ok(Ok(())) => Status::Passed,
+        Ok(Err(err)) => {
+            eprintln!("Error: {}", err);
+            if report::has_failures() {
+                Status::Failed
+            } else {
+                err.status()
             }
-            output("It is.");
-
-            output(&format!("Activating feature {}...", feature_id));
-            context.activate_feature(&feature_id).await;
-
-            context.wait_for_next_epoch().await;
-
-            output("Checking to see if program is now a BPF program...");
-            context.assert_program_is_bpf(&program_id).await;
-            output("It is.");
-
-            context.wait_for_next_slot().await;
-
-            output("Running stub tests on the BPF program...");
-            context.run_stub_tests(&program_id).await;
-            output("Success.");
+        }
+        Err(panic) => match panic.downcast::<AssertionFailure>() {
+            // Checks and assertions panic with an `AssertionFailure` when a
+            // test fails.
+            Ok(failure) => {
+                report::record("Test aborted", &Err(failure.0));
+                Status::Failed
+            }
+            // Any other panic is the harness itself failing.
+            Err(panic) => {
+                let message = panic
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .or_else(|| panic.downcast_ref::<&str>().map(ToString::to_string))
+                    .unwrap_or_default();
+                report::record_error("Test aborted", message);
+                Status::Errored
+            }
+        },
+    };
 
-            context.wait_for_next_epoch().await;
+    if let Err(err) = report::write_reports(&cli.report, &cli.report_dir, status) {
+        eprintln!("Error: {}", err);
+        return ExitCode::from(err.status().exit_code());
+    }
+    ExitCode::from(status.exit_code())
+}
 
-            output("Running stub tests again on the BPF program...");
-            context.run_stub_tests(&program_id).await;
-            output("Success.");
+async fn run(command: SubCommand, registry: Option<PathBuf>) -> Result<(), CbmtError> {
+    let registry = Registry::load(registry.as_deref()).map_err(CbmtError::Config)?;
 
-            output("Test complete! Woohoo!");
+    match command {
+        SubCommand::Stub {
+            programs,
+            all,
+            stagger,
+            real_elf,
+            preserve_state,
+            failure_mode,
+            accounting,
+            timing_matrix,
+            revoke_first,
+            custom,
+            source,
+            validator,
+        } => {
+            let names = if all {
+                registry
+                    .programs()
+                    .iter()
+                    .map(|program| program.name().to_string())
+                    .collect()
+            } else {
+                programs
+            };
+            let programs = registry
+                .resolve_all(&names, &custom)
+                .map_err(CbmtError::Config)?;
+
+            run_stub_test(
+                &programs,
+                &StubOptions {
+                    validator,
+                    stagger,
+                    source: real_elf.then(|| source.source()),
+                    preserve_state,
+                    failure_modes: failure_mode,
+                    accounting,
+                    timing_matrix,
+                    revoke_first,
+                },
+            )
+            .await?;
+        }
+        SubCommand::Cu {
+            program,
+            custom,
+            source,
+            thresholds,
+            validator,
+        } => {
+            let program = registry
+                .resolve(&program, &custom)
+                .map_err(CbmtError::Config)?;
+            run_cu_comparison(&program, &source.source(), &validator, &thresholds).await?;
         }
         SubCommand::Fixtures {
             program,
-            cluster,
+            custom,
+            source,
             use_mollusk_fixtures,
             skip_setup,
+            filter,
+            cache,
         } => {
+            let program = registry
+                .resolve(&program, &custom)
+                .map_err(CbmtError::Config)?;
+            program
+                .check_fixtures_source(use_mollusk_fixtures)
+                .map_err(CbmtError::Config)?;
+            let filter = FixtureFilter::load(&program, &filter).map_err(CbmtError::Config)?;
+            let cache = DependencyCache::open(&cache)?;
+            let source = source.source();
             title_fixtures_test(
-                &cluster.to_string(),
-                &program.buffer_address(),
+                &source.to_string(),
+                &source.buffer_address(&program),
                 use_mollusk_fixtures,
             );
 
-            output(&format!("Cloning ELF from {}...", &cluster.to_string()));
-            let elf = clone_elf_from_buffer_account(&cluster, &program).await;
+            output(&format!("Loading ELF from {}...", &source));
+            let elf = source.load(&program).await?;
 
             output("Initializing test environment...");
             let mut handler = if skip_setup {
-                ConformanceHandler::no_setup(&program, ELF_DIRECTORY, use_mollusk_fixtures)
+                ConformanceHandler::no_setup(&program, ELF_DIRECTORY, use_mollusk_fixtures, &cache)?
             } else {
-                ConformanceHandler::setup(&program, ELF_DIRECTORY, use_mollusk_fixtures)
+                ConformanceHandler::setup(&program, ELF_DIRECTORY, use_mollusk_fixtures, &cache)?
             };
 
             output("Bulding target...");
-            write_elf_to_file(elf, &program.elf_name());
-            handler.build_conformance_target_bpf(/* conformance_mode */ false);
+            write_elf_to_file(elf, &program.elf_name())?;
+            handler.build_conformance_target_bpf(/* conformance_mode */ false)?;
 
             output("Running fixtures...");
-            handler.run_fixtures();
+            let results = handler.run_fixtures(&filter)?;
+            report_results(&results).map_err(CbmtError::Fixture)?;
 
             output("Test complete! Woohoo!");
         }
         SubCommand::Conformance {
             program,
-            cluster,
+            custom,
+            source,
             use_mollusk_fixtures,
             skip_setup,
+            filter,
+            cache,
         } => {
+            let program = registry
+                .resolve(&program, &custom)
+                .map_err(CbmtError::Config)?;
+            program
+                .check_fixtures_source(use_mollusk_fixtures)
+                .map_err(CbmtError::Config)?;
+            let filter = FixtureFilter::load(&program, &filter).map_err(CbmtError::Config)?;
+            let cache = DependencyCache::open(&cache)?;
+            let source = source.source();
             title_conformance_test(
-                &cluster.to_string(),
-                &program.buffer_address(),
+                &source.to_string(),
+                &source.buffer_address(&program),
                 use_mollusk_fixtures,
             );
 
-            output(&format!("Cloning ELF from {}...", &cluster.to_string()));
-            let elf = clone_elf_from_buffer_account(&cluster, &program).await;
+            output(&format!("Loading ELF from {}...", &source));
+            let elf = source.load(&program).await?;
 
             output("Initializing test environment...");
             let mut handler = if skip_setup {
-                ConformanceHandler::no_setup(&program, ELF_DIRECTORY, use_mollusk_fixtures)
+                ConformanceHandler::no_setup(&program, ELF_DIRECTORY, use_mollusk_fixtures, &cache)?
             } else {
-                ConformanceHandler::setup(&program, ELF_DIRECTORY, use_mollusk_fixtures)
+                ConformanceHandler::setup(&program, ELF_DIRECTORY, use_mollusk_fixtures, &cache)?
             };
 
             output("Bulding targets...");
-            write_elf_to_file(elf, &program.elf_name());
-            handler.build_conformance_target_builtin();
-            handler.build_conformance_target_bpf(/* conformance_mode */ true);
+            write_elf_to_file(elf, &program.elf_name())?;
+            handler.build_conformance_target_builtin()?;
+            handler.build_conformance_target_bpf(/* conformance_mode */ true)?;
 
             output("Running conformance tests...");
-            handler.run_conformance();
+            let results = handler.run_conformance(&filter)?;
+            report_results(&results).map_err(CbmtError::Fixture)?;
 
             output("Test complete! Woohoo!");
         }
+        SubCommand::FetchDeps { update, cache } => {
+            fetch_deps(registry.programs(), &cache, update)?;
+        }
+        SubCommand::Inspect {
+            program,
+            custom,
+            source,
+        } => {
+            let program = registry
+                .resolve(&program, &custom)
+                .map_err(CbmtError::Config)?;
+            let source = source.source();
+            title_inspect(&source.to_string(), &source.buffer_address(&program));
+
+            output(&format!("Loading ELF from {}...", &source));
+            let elf = source.load(&program).await?;
+
+            output("Inspecting ELF...");
+            let inspection = inspect_elf(&elf).map_err(CbmtError::Validation)?;
+            inspection.print();
+
+            check_verdict(
+                "ELF is deployable",
+                inspection.is_deployable(),
+                "ELF would fail to deploy against the linked runtime",
+            )?;
+
+            output("Inspection complete! Woohoo!");
+        }
+        SubCommand::Diff {
+            program,
+            custom,
+            from,
+            from_elf,
+            to,
+            to_elf,
+            rpc,
+        } => {
+            // Hashes are compared directly, so any pins are not enforced.
+            let program = registry
+                .resolve(&program, &custom)
+                .map_err(CbmtError::Config)?
+                .unpinned();
+            let from = match from_elf {
+                Some(path) => ElfSource::Elf(path),
+                None => ElfSource::Cluster(from, rpc.clone()),
+            };
+            let to = match to_elf {
+                Some(path) => ElfSource::Elf(path),
+                None => ElfSource::Cluster(to, rpc),
+            };
+            title_diff(&program.to_string(), &from.to_string(), &to.to_string());
+
+            output(&format!("Loading ELF from {}...", &from));
+            let (from_elf, from_raw) = from.load_with_padding(&program).await?;
+
+            output(&format!("Loading ELF from {}...", &to));
+            let (to_elf, to_raw) = to.load_with_padding(&program).await?;
+
+            output("Comparing ELFs...");
+            let identical = diff_elfs(&from.to_string(), &from_elf, &to.to_string(), &to_elf)
+                .map_err(CbmtError::Validation)?;
+            // Local ELFs have no buffer data to compare beyond the ELF.
+            let buffers_identical = match (&from, &to) {
+                (ElfSource::Elf(_), _) | (_, ElfSource::Elf(_)) => None,
+                _ => Some(diff_raw(
+                    &from.to_string(),
+                    &from_raw,
+                    &to.to_string(),
+                    &to_raw,
+                )),
+            };
+
+            check_verdict("ELFs are identical", identical, "ELFs differ")?;
+            if let Some(buffers_identical) = buffers_identical {
+                check_verdict(
+                    "Buffer data is identical",
+                    buffers_identical,
+                    "ELFs are identical, but the buffer data after them differs",
+                )?;
+            }
+
+            output("ELFs are byte-identical! Woohoo!");
+        }
+        SubCommand::VerifyBuild {
+            program,
+            custom,
+            repo_path,
+            commit,
+            library_name,
+            source,
+        } => {
+            let program = registry
+                .resolve(&program, &custom)
+                .map_err(CbmtError::Config)?;
+            let source = source.source();
+            let library_name = library_name.unwrap_or_else(|| program.library_name());
+            title_verify_build(
+                &source.to_string(),
+                &source.buffer_address(&program),
+                &repo_path.display().to_string(),
+                &commit,
+            );
+
+            output(&format!("Loading ELF from {}...", &source));
+            let buffer_elf = source.load(&program).await?;
+
+            output(&format!("Checking out {}...", &commit));
+            let checkout = checkout_commit(&repo_path, &commit, &program)?;
+
+            output(&format!("Building {}...", &library_name));
+            let built_elf_path = verifiable_build(&repo_path, &library_name);
+
+            output(&format!("Checking out {} again...", checkout.previous()));
+            checkout.restore()?;
+            let built_elf_path = built_elf_path?;
+            let built_elf = std::fs::read(&built_elf_path).map_err(|err| {
+                CbmtError::Harness(format!(
+                    "Failed to read built ELF {}: {}",
+                    built_elf_path.display(),
+                    err
+                ))
+            })?;
+
+            output("Comparing ELFs...");
+            check_verdict(
+                "Build matches the buffer ELF",
+                compare_build(&built_elf, &buffer_elf),
+                "Built ELF does not match the buffer ELF",
+            )?;
+
+            output("Build reproduced! Woohoo!");
+        }
     }
 
     Ok(())
 }
 
-fn cargo_build_sbf(manifest_path: &str) {
-    Command::new("cargo")
-        .arg("build-sbf")
-        .arg("--manifest-path")
-        .arg(manifest_path)
-        .arg("--features")
-        .arg("sbf-entrypoint")
-        .arg("--sbf-out-dir")
-        .arg(ELF_DIRECTORY)
-        .status()
-        .expect("Failed to build crate");
-}
-
-async fn clone_elf_from_buffer_account(cluster: &Cluster, program: &Program) -> Vec<u8> {
-    let rpc_client = RpcClient::new(cluster.url().to_string());
-    let account = rpc_client
-        .get_account(&program.buffer_address())
-        .await
-        .expect("Account not found");
-    if account.data.len() < UpgradeableLoaderState::size_of_buffer_metadata() {
-        panic!("Buffer account is too small");
-    }
-    account.data[UpgradeableLoaderState::size_of_buffer_metadata()..].to_vec()
+/// Record a command's verdict, failing the command if it's negative.
+fn check_verdict(name: &str, passed: bool, err: &str) -> Result<(), CbmtError> {
+    let result = if passed { Ok(()) } else { Err(err.to_string()) };
+    report::record(name, &result);
+    result.map_err(CbmtError::Validation)
 }
 
-fn write_elf_to_file(elf: Vec<u8>, elf_name: &str) {
-    std::fs::create_dir_all(ELF_DIRECTORY).unwrap();
+fn write_elf_to_file(elf: Vec<u8>, elf_name: &str) -> Result<(), CbmtError> {
     let path = Path::new(ELF_DIRECTORY).join(elf_name);
-    let mut file = File::create(path).expect("Failed to create ELF file");
-    file.write_all(&elf).expect("Failed to write ELF to file");
+    let write_error = |err: std::io::Error| {
+        CbmtError::Harness(format!("Failed to write {}: {}", path.display(), err))
+    };
+    std::fs::create_dir_all(ELF_DIRECTORY).map_err(write_error)?;
+    let mut file = File::create(&path).map_err(write_error)?;
+    file.write_all(&elf).map_err(write_error)
 }
@@ -16,19 +16,23 @@ fn write(stdout: &mut StandardStream, msg: &str, color: Option<Color>) {
 }
 
 #[rustfmt::skip]
-pub fn title_stub_test(feature_id: &Pubkey, buffer_address: &Pubkey) {
-    let feature_description: String = FEATURE_NAMES.get(feature_id).expect("Feature not found").chars().take(160).collect();
-    
+pub fn title_stub_test(targets: &[(Pubkey, Pubkey)], source: &str) {
     let mut stdout = StandardStream::stdout(ColorChoice::Always);
     writeln!(&mut stdout).unwrap();
     writeln!(&mut stdout, "    =============================================").unwrap();
     writeln!(&mut stdout, "    Core BPF Migration Test: Stub Test").unwrap();
     writeln!(&mut stdout).unwrap();
-    writeln!(&mut stdout, "    Description   : {}", feature_description).unwrap();
-    writeln!(&mut stdout).unwrap();
-    writeln!(&mut stdout, "    Feature ID    : {}", feature_id).unwrap();
-    writeln!(&mut stdout).unwrap();
-    writeln!(&mut stdout, "    Buffer Address: {}", buffer_address).unwrap();
+    writeln!(&mut stdout, "    ELF Source    : {}", source).unwrap();
+    for (feature_id, buffer_address) in targets {
+        let feature_description: String = FEATURE_NAMES.get(feature_id).copied().unwrap_or("Unknown feature").chars().take(160).collect();
+
+        writeln!(&mut stdout).unwrap();
+        writeln!(&mut stdout, "    Description   : {}", feature_description).unwrap();
+        writeln!(&mut stdout).unwrap();
+        writeln!(&mut stdout, "    Feature ID    : {}", feature_id).unwrap();
+        writeln!(&mut stdout).unwrap();
+        writeln!(&mut stdout, "    Buffer Address: {}", buffer_address).unwrap();
+    }
     writeln!(&mut stdout, "    =============================================").unwrap();
     writeln!(&mut stdout).unwrap();
     writeln!(&mut stdout).unwrap();
@@ -36,7 +40,7 @@ pub fn title_stub_test(feature_id: &Pubkey, buffer_address: &Pubkey) {
 }
 
 #[rustfmt::skip]
-pub fn title_fixtures_test(cluster: &str, buffer_address: &Pubkey, use_mollusk_fixtures: bool) {
+pub fn title_fixtures_test(source: &str, buffer_address: &Pubkey, use_mollusk_fixtures: bool) {
     let mut stdout = StandardStream::stdout(ColorChoice::Always);
     writeln!(&mut stdout).unwrap();
     writeln!(&mut stdout, "    =============================================").unwrap();
@@ -44,7 +48,7 @@ pub fn title_fixtures_test(cluster: &str, buffer_address: &Pubkey, use_mollusk_f
     writeln!(&mut stdout).unwrap();
     writeln!(&mut stdout, "    Buffer Address: {}", buffer_address).unwrap();
     writeln!(&mut stdout).unwrap();
-    writeln!(&mut stdout, "    Cloning From  : {}", cluster).unwrap();
+    writeln!(&mut stdout, "    ELF Source    : {}", source).unwrap();
     writeln!(&mut stdout).unwrap();
     let fixtures = if use_mollusk_fixtures { "Mollusk" } else { "Firedancer" };
     writeln!(&mut stdout, "    Fixtures      : {}", fixtures).unwrap();
@@ -55,7 +59,7 @@ pub fn title_fixtures_test(cluster: &str, buffer_address: &Pubkey, use_mollusk_f
 }
 
 #[rustfmt::skip]
-pub fn title_conformance_test(cluster: &str, buffer_address: &Pubkey, use_mollusk_fixtures: bool) {
+pub fn title_conformance_test(source: &str, buffer_address: &Pubkey, use_mollusk_fixtures: bool) {
     let mut stdout = StandardStream::stdout(ColorChoice::Always);
     writeln!(&mut stdout).unwrap();
     writeln!(&mut stdout, "    =============================================").unwrap();
@@ -63,7 +67,7 @@ pub fn title_conformance_test(cluster: &str, buffer_address: &Pubkey, use_mollus
     writeln!(&mut stdout).unwrap();
     writeln!(&mut stdout, "    Buffer Address: {}", buffer_address).unwrap();
     writeln!(&mut stdout).unwrap();
-    writeln!(&mut stdout, "    Cloning From  : {}", cluster).unwrap();
+    writeln!(&mut stdout, "    ELF Source    : {}", source).unwrap();
     writeln!(&mut stdout).unwrap();
     let fixtures = if use_mollusk_fixtures { "Mollusk" } else { "Firedancer" };
     writeln!(&mut stdout, "    Fixtures      : {}", fixtures).unwrap();
@@ -73,6 +77,76 @@ pub fn title_conformance_test(cluster: &str, buffer_address: &Pubkey, use_mollus
     stdout.reset().unwrap();
 }
 
+#[rustfmt::skip]
+pub fn title_inspect(source: &str, buffer_address: &Pubkey) {
+    let mut stdout = StandardStream::stdout(ColorChoice::Always);
+    writeln!(&mut stdout).unwrap();
+    writeln!(&mut stdout, "    =============================================").unwrap();
+    writeln!(&mut stdout, "    Core BPF Migration Test: ELF Inspection").unwrap();
+    writeln!(&mut stdout).unwrap();
+    writeln!(&mut stdout, "    Buffer Address: {}", buffer_address).unwrap();
+    writeln!(&mut stdout).unwrap();
+    writeln!(&mut stdout, "    ELF Source    : {}", source).unwrap();
+    writeln!(&mut stdout, "    =============================================").unwrap();
+    writeln!(&mut stdout).unwrap();
+    writeln!(&mut stdout).unwrap();
+    stdout.reset().unwrap();
+}
+
+#[rustfmt::skip]
+pub fn title_diff(program: &str, from: &str, to: &str) {
+    let mut stdout = StandardStream::stdout(ColorChoice::Always);
+    writeln!(&mut stdout).unwrap();
+    writeln!(&mut stdout, "    =============================================").unwrap();
+    writeln!(&mut stdout, "    Core BPF Migration Test: ELF Diff").unwrap();
+    writeln!(&mut stdout).unwrap();
+    writeln!(&mut stdout, "    Program       : {}", program).unwrap();
+    writeln!(&mut stdout).unwrap();
+    writeln!(&mut stdout, "    From          : {}", from).unwrap();
+    writeln!(&mut stdout).unwrap();
+    writeln!(&mut stdout, "    To            : {}", to).unwrap();
+    writeln!(&mut stdout, "    =============================================").unwrap();
+    writeln!(&mut stdout).unwrap();
+    writeln!(&mut stdout).unwrap();
+    stdout.reset().unwrap();
+}
+
+#[rustfmt::skip]
+pub fn title_verify_build(source: &str, buffer_address: &Pubkey, repo_path: &str, commit: &str) {
+    let mut stdout = StandardStream::stdout(ColorChoice::Always);
+    writeln!(&mut stdout).unwrap();
+    writeln!(&mut stdout, "    =============================================").unwrap();
+    writeln!(&mut stdout, "    Core BPF Migration Test: Verify Build").unwrap();
+    writeln!(&mut stdout).unwrap();
+    writeln!(&mut stdout, "    Buffer Address: {}", buffer_address).unwrap();
+    writeln!(&mut stdout).unwrap();
+    writeln!(&mut stdout, "    ELF Source    : {}", source).unwrap();
+    writeln!(&mut stdout).unwrap();
+    writeln!(&mut stdout, "    Repository    : {}", repo_path).unwrap();
+    writeln!(&mut stdout).unwrap();
+    writeln!(&mut stdout, "    Commit        : {}", commit).unwrap();
+    writeln!(&mut stdout, "    =============================================").unwrap();
+    writeln!(&mut stdout).unwrap();
+    writeln!(&mut stdout).unwrap();
+    stdout.reset().unwrap();
+}
+
+#[rustfmt::skip]
+pub fn title_cu(source: &str, buffer_address: &Pubkey) {
+    let mut stdout = StandardStream::stdout(ColorChoice::Always);
+    writeln!(&mut stdout).unwrap();
+    writeln!(&mut stdout, "    =============================================").unwrap();
+    writeln!(&mut stdout, "    Core BPF Migration Test: Compute Units").unwrap();
+    writeln!(&mut stdout).unwrap();
+    writeln!(&mut stdout, "    Buffer Address: {}", buffer_address).unwrap();
+    writeln!(&mut stdout).unwrap();
+    writeln!(&mut stdout, "    ELF Source    : {}", source).unwrap();
+    writeln!(&mut stdout, "    =============================================").unwrap();
+    writeln!(&mut stdout).unwrap();
+    writeln!(&mut stdout).unwrap();
+    stdout.reset().unwrap();
+}
+
 pub fn output(msg: &str) {
     let mut stdout = StandardStream::stdout(ColorChoice::Always);
     writeln!(&mut stdout).unwrap();
@@ -84,3 +158,17 @@ pub fn output(msg: &str) {
     writeln!(&mut stdout).unwrap();
     stdout.reset().unwrap();
 }
+
+/// Print a warning that must not be missed in the output, such as a check
+/// that could not be performed.
+pub fn warning(msg: &str) {
+    let mut stdout = StandardStream::stdout(ColorChoice::Always);
+    writeln!(&mut stdout).unwrap();
+    write(&mut stdout, "  [", None);
+    write(&mut stdout, "WARNING", Some(Color::Yellow));
+    write(&mut stdout, "]: ", None);
+    write(&mut stdout, msg, Some(Color::Yellow));
+    writeln!(&mut stdout).unwrap();
+    writeln!(&mut stdout).unwrap();
+    stdout.reset().unwrap();
+}
@@ -1,116 +1,388 @@
 //! Program wrapper.
 
-use {solana_sdk::pubkey::Pubkey, std::str::FromStr};
+use {
+    crate::cluster::Cluster,
+    clap::Args,
+    serde::Deserialize,
+    solana_sdk::pubkey::Pubkey,
+    std::{collections::HashMap, str::FromStr},
+};
 
+/// The program name reserved for ad-hoc migration targets, described entirely
+/// by [`CustomProgramArgs`].
+pub const CUSTOM_PROGRAM_NAME: &str = "custom";
+
+/// A program registered for Core BPF migration testing.
 #[derive(Clone)]
-pub enum Program {
-    AddressLookupTable,
-    Config,
-    FeatureGate,
+pub struct Program {
+    name: String,
+    program_id: Pubkey,
+    feature_id: Pubkey,
+    buffer_address: Pubkey,
+    cluster_buffer_addresses: HashMap<String, Pubkey>,
+    fixtures_path: Option<String>,
+    repository: Option<String>,
+    buffer_authority: Option<ExpectedAuthority>,
+    elf_hash: Option<String>,
+    pins_waived: bool,
+    library_name: Option<String>,
 }
 
-impl Program {
-    pub const fn buffer_address(&self) -> Pubkey {
+/// The authority a buffer account is expected to have.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ExpectedAuthority {
+    None,
+    Some(Pubkey),
+}
+
+impl FromStr for ExpectedAuthority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            _ => Pubkey::from_str(s)
+                .map(Self::Some)
+                .map_err(|_| format!("Invalid buffer authority: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for ExpectedAuthority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::AddressLookupTable => {
-                solana_sdk::pubkey!("AhXWrD9BBUYcKjtpA3zuiiZG4ysbo6C6wjHo1QhERk6A")
-            }
-            Self::Config => solana_sdk::pubkey!("BuafH9fBv62u6XjzrzS4ZjAE8963ejqF5rt1f8Uga4Q3"),
-            Self::FeatureGate => {
-                solana_sdk::pubkey!("3D3ydPWvmEszrSjrickCtnyRSJm1rzbbSsZog8Ub6vLh")
-            }
+            Self::None => write!(f, "none"),
+            Self::Some(authority) => write!(f, "{}", authority),
         }
     }
+}
 
-    pub fn elf_name(&self) -> String {
-        format!("{}.so", self.name_snake_case())
+impl Program {
+    /// The programs shipped with the CLI. Their buffer authorities and ELF
+    /// hashes are not pinned yet, so each verification warns that neither was
+    /// checked, unless a registry file pins them.
+    pub fn builtins() -> Vec<Self> {
+        vec![
+            Self {
+                name: "address-lookup-table".to_string(),
+                program_id: solana_sdk::address_lookup_table::program::ID,
+                feature_id:
+                    solana_sdk::feature_set::migrate_address_lookup_table_program_to_core_bpf::ID,
+                buffer_address: solana_sdk::pubkey!("AhXWrD9BBUYcKjtpA3zuiiZG4ysbo6C6wjHo1QhERk6A"),
+                cluster_buffer_addresses: HashMap::new(),
+                fixtures_path: Some("instr/fixtures/address-lookup-table".to_string()),
+                repository: Some(default_repository("address-lookup-table")),
+                buffer_authority: None,
+                elf_hash: None,
+                pins_waived: false,
+                library_name: None,
+            },
+            Self {
+                name: "config".to_string(),
+                program_id: solana_sdk::config::program::ID,
+                feature_id: solana_sdk::feature_set::migrate_config_program_to_core_bpf::ID,
+                buffer_address: solana_sdk::pubkey!("BuafH9fBv62u6XjzrzS4ZjAE8963ejqF5rt1f8Uga4Q3"),
+                cluster_buffer_addresses: HashMap::new(),
+                fixtures_path: Some("instr/fixtures/config".to_string()),
+                repository: Some(default_repository("config")),
+                buffer_authority: None,
+                elf_hash: None,
+                pins_waived: false,
+                library_name: None,
+            },
+            Self {
+                name: "feature-gate".to_string(),
+                program_id: solana_sdk::feature::ID,
+                feature_id: solana_sdk::feature_set::migrate_feature_gate_program_to_core_bpf::ID,
+                buffer_address: solana_sdk::pubkey!("3D3ydPWvmEszrSjrickCtnyRSJm1rzbbSsZog8Ub6vLh"),
+                cluster_buffer_addresses: HashMap::new(),
+                fixtures_path: Some("instr/fixtures/feature-gate".to_string()),
+                repository: Some(default_repository("feature-gate")),
+                buffer_authority: None,
+                elf_hash: None,
+                pins_waived: false,
+                library_name: None,
+            },
+        ]
     }
 
-    pub const fn feature_gate(&self) -> Pubkey {
-        match self {
-            Self::AddressLookupTable => {
-                solana_sdk::feature_set::migrate_address_lookup_table_program_to_core_bpf::ID
-            }
-            Self::Config => solana_sdk::feature_set::migrate_config_program_to_core_bpf::ID,
-            Self::FeatureGate => {
-                solana_sdk::feature_set::migrate_feature_gate_program_to_core_bpf::ID
-            }
-        }
+    /// The buffer address used when no cluster-specific address is
+    /// registered.
+    pub fn buffer_address(&self) -> Pubkey {
+        self.buffer_address
     }
 
-    pub const fn fixtures_path(&self) -> &str {
-        match self {
-            Self::AddressLookupTable => "instr/fixtures/address-lookup-table",
-            Self::Config => "instr/fixtures/config",
-            Self::FeatureGate => "instr/fixtures/feature-gate",
-        }
+    /// The buffer address for the provided cluster, falling back to the
+    /// default buffer address.
+    pub fn buffer_address_on(&self, cluster: &Cluster) -> Pubkey {
+        self.cluster_buffer_addresses
+            .get(&cluster.to_string())
+            .copied()
+            .unwrap_or(self.buffer_address)
     }
 
-    fn name_snake_case(&self) -> &str {
-        match self {
-            Self::AddressLookupTable => "address_lookup_table",
-            Self::Config => "config",
-            Self::FeatureGate => "feature_gate",
+    /// A copy of the program without its buffer authority and ELF hash pins,
+    /// for callers that check the ELF some other way. Missing pins are not
+    /// warned about.
+    pub fn unpinned(&self) -> Self {
+        Self {
+            buffer_authority: None,
+            elf_hash: None,
+            pins_waived: true,
+            ..self.clone()
         }
     }
 
-    pub const fn program_id(&self) -> Pubkey {
-        match self {
-            Self::AddressLookupTable => solana_sdk::address_lookup_table::program::ID,
-            Self::Config => solana_sdk::config::program::ID,
-            Self::FeatureGate => solana_sdk::feature::ID,
-        }
+    /// The expected authority of the buffer account, if pinned.
+    pub fn buffer_authority(&self) -> Option<ExpectedAuthority> {
+        self.buffer_authority
     }
 
-    pub fn repository(&self) -> String {
-        format!(
-            "https://github.com/solana-program/{}.git",
-            &self.to_string()
-        )
+    /// The expected SHA-256 hash of the buffer ELF, if pinned.
+    pub fn elf_hash(&self) -> Option<&str> {
+        self.elf_hash.as_deref()
     }
 
-    pub fn skip_conformance_fixtures(&self) -> Vec<&str> {
-        match self {
-            Self::AddressLookupTable => vec![
-                "6d8f5dc4bb073f6ae72a950b5108c82b41c6347a_3246919",
-                "9017cf61dc0da7aa28a0b63a058f685e87df1e9a_2789718",
-                "9c02f3e6bc4f519ed342f4a017a4d7050faef079_2789718",
-                "9d3983516dd9cc4d515bf05f98011f22935093a4_3246919",
-                "c328874b96d05db6bacb01c6534e43c1c065f3bd_3246919",
-                "e5474fe3b664271f922437a3c707dc7d537d91ec_2789718",
-            ],
-            Self::Config => vec![
-                "04a0b782cb1f4b1be044313331edda9dfb4696d6",
-                "c7ec10c03d5faadcebd32dc5b9a4086abef892ca_3157979",
-                "68e8dbf0f31de69a2bd1d2c0fe9af3ba676301d6_3157979",
-                "f84b5ad44f7a253ebc8056d06396694370a7fa4c_3157979",
-                "8bbe900444c675cfc3fbf0f80ae2eb061e536a09",
-            ],
-            Self::FeatureGate => vec![],
+    /// Whether missing pins were deliberately waived with [`Self::unpinned`].
+    pub fn pins_waived(&self) -> bool {
+        self.pins_waived
+    }
+
+    pub fn elf_name(&self) -> String {
+        format!("{}.so", self.elf_stem())
+    }
+
+    /// The name of the program's ELF file, without the `.so` extension.
+    pub fn elf_stem(&self) -> String {
+        self.name_snake_case()
+    }
+
+    pub fn feature_gate(&self) -> Pubkey {
+        self.feature_id
+    }
+
+    /// The name of the library produced by building the program's
+    /// repository, defaulting to `solana_<name>_program`.
+    pub fn library_name(&self) -> String {
+        self.library_name
+            .clone()
+            .unwrap_or_else(|| format!("solana_{}_program", self.name_snake_case()))
+    }
+
+    pub fn fixtures_path(&self) -> Option<&str> {
+        self.fixtures_path.as_deref()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn name_snake_case(&self) -> String {
+        self.name.replace('-', "_")
+    }
+
+    pub fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    pub fn repository(&self) -> Option<&str> {
+        self.repository.as_deref()
+    }
+
+    /// Ensure the program declares the source of the fixtures to be run
+    /// against it.
+    pub fn check_fixtures_source(&self, use_mollusk_fixtures: bool) -> Result<(), String> {
+        if use_mollusk_fixtures && self.repository.is_none() {
+            return Err(format!(
+                "Program `{}` has no repository to source Mollusk fixtures from",
+                self.name
+            ));
+        }
+        if !use_mollusk_fixtures && self.fixtures_path.is_none() {
+            return Err(format!(
+                "Program `{}` has no Firedancer fixtures path",
+                self.name
+            ));
         }
+        Ok(())
     }
 }
 
-impl FromStr for Program {
-    type Err = String;
+fn default_repository(name: &str) -> String {
+    format!("https://github.com/solana-program/{}.git", name)
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "address-lookup-table" => Ok(Self::AddressLookupTable),
-            "config" => Ok(Self::Config),
-            "feature-gate" => Ok(Self::FeatureGate),
-            _ => Err(format!("Invalid program name: {}", s)),
-        }
+fn parse_elf_hash(hash: &str) -> Result<String, String> {
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid ELF hash (expected SHA-256 hex): {}", hash));
     }
+    Ok(hash.to_ascii_lowercase())
 }
 
 impl std::fmt::Display for Program {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let s = match self {
-            Self::AddressLookupTable => "address-lookup-table",
-            Self::Config => "config",
-            Self::FeatureGate => "feature-gate",
+        write!(f, "{}", self.name)
+    }
+}
+
+/// A program entry, as declared in a registry file.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProgramEntry {
+    name: String,
+    program_id: String,
+    feature_id: String,
+    buffer_address: String,
+    #[serde(default)]
+    cluster_buffer_addresses: HashMap<String, String>,
+    fixtures_path: Option<String>,
+    repository: Option<String>,
+    buffer_authority: Option<String>,
+    elf_hash: Option<String>,
+    library_name: Option<String>,
+}
+
+impl TryFrom<ProgramEntry> for Program {
+    type Error = String;
+
+    fn try_from(entry: ProgramEntry) -> Result<Self, Self::Error> {
+        let name = entry.name;
+        if name == CUSTOM_PROGRAM_NAME {
+            return Err(format!(
+                "Program name `{}` is reserved for ad-hoc migration targets",
+                name
+            ));
+        }
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        {
+            return Err(format!(
+                "Invalid program name `{}`: must be non-empty kebab-case",
+                name
+            ));
+        }
+
+        let parse_pubkey = |field: &str, value: &str| {
+            Pubkey::from_str(value)
+                .map_err(|_| format!("Program `{}`: invalid {}: {}", name, field, value))
         };
-        write!(f, "{}", s)
+
+        let program_id = parse_pubkey("program-id", &entry.program_id)?;
+        let feature_id = parse_pubkey("feature-id", &entry.feature_id)?;
+        let buffer_address = parse_pubkey("buffer-address", &entry.buffer_address)?;
+
+        let mut cluster_buffer_addresses = HashMap::new();
+        for (cluster, address) in entry.cluster_buffer_addresses {
+            let cluster = Cluster::from_str(&cluster)
+                .map_err(|err| format!("Program `{}`: {}", name, err))?;
+            let address = parse_pubkey("cluster buffer address", &address)?;
+            cluster_buffer_addresses.insert(cluster.to_string(), address);
+        }
+
+        if entry.fixtures_path.as_deref() == Some("") {
+            return Err(format!("Program `{}`: fixtures-path cannot be empty", name));
+        }
+        if entry.repository.as_deref() == Some("") {
+            return Err(format!("Program `{}`: repository cannot be empty", name));
+        }
+
+        let buffer_authority = entry
+            .buffer_authority
+            .as_deref()
+            .map(ExpectedAuthority::from_str)
+            .transpose()
+            .map_err(|err| format!("Program `{}`: {}", name, err))?;
+        let elf_hash = entry
+            .elf_hash
+            .map(|hash| parse_elf_hash(&hash))
+            .transpose()
+            .map_err(|err| format!("Program `{}`: {}", name, err))?;
+
+        let repository = entry
+            .repository
+            .unwrap_or_else(|| default_repository(&name));
+
+        Ok(Self {
+            name,
+            program_id,
+            feature_id,
+            buffer_address,
+            cluster_buffer_addresses,
+            fixtures_path: entry.fixtures_path,
+            repository: Some(repository),
+            buffer_authority,
+            elf_hash,
+            pins_waived: false,
+            library_name: entry.library_name,
+        })
+    }
+}
+
+/// Flags describing an ad-hoc migration target, for testing a candidate
+/// buffer before its program is registered.
+#[derive(Args, Default)]
+pub struct CustomProgramArgs {
+    /// The program ID of a `custom` migration target.
+    #[arg(long)]
+    pub program_id: Option<Pubkey>,
+    /// The feature ID of a `custom` migration target.
+    #[arg(long)]
+    pub feature_id: Option<Pubkey>,
+    /// The buffer address of a `custom` migration target.
+    #[arg(long)]
+    pub buffer_address: Option<Pubkey>,
+    /// The repository of a `custom` migration target, used to source Mollusk
+    /// fixtures.
+    #[arg(long)]
+    pub repository: Option<String>,
+    /// The Firedancer fixtures path of a `custom` migration target.
+    #[arg(long)]
+    pub fixtures_path: Option<String>,
+    /// The expected buffer authority of a `custom` migration target, or
+    /// `none`.
+    #[arg(long)]
+    pub buffer_authority: Option<ExpectedAuthority>,
+    /// The expected SHA-256 hash of a `custom` migration target's ELF.
+    #[arg(long, value_parser = parse_elf_hash)]
+    pub elf_hash: Option<String>,
+}
+
+impl CustomProgramArgs {
+    pub fn is_empty(&self) -> bool {
+        self.program_id.is_none()
+            && self.feature_id.is_none()
+            && self.buffer_address.is_none()
+            && self.repository.is_none()
+            && self.fixtures_path.is_none()
+            && self.buffer_authority.is_none()
+            && self.elf_hash.is_none()
+    }
+
+    /// Build an ad-hoc migration target from the provided flags.
+    pub fn to_program(&self) -> Result<Program, String> {
+        let require = |value: Option<Pubkey>, flag: &str| {
+            value.ok_or_else(|| {
+                format!(
+                    "The `{}` program requires `--{}`",
+                    CUSTOM_PROGRAM_NAME, flag
+                )
+            })
+        };
+
+        Ok(Program {
+            name: CUSTOM_PROGRAM_NAME.to_string(),
+            program_id: require(self.program_id, "program-id")?,
+            feature_id: require(self.feature_id, "feature-id")?,
+            buffer_address: require(self.buffer_address, "buffer-address")?,
+            cluster_buffer_addresses: HashMap::new(),
+            fixtures_path: self.fixtures_path.clone(),
+            repository: self.repository.clone(),
+            buffer_authority: self.buffer_authority,
+            elf_hash: self.elf_hash.clone(),
+            pins_waived: false,
+            library_name: None,
+        })
     }
 }
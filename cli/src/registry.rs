@@ -0,0 +1,232 @@
+//! Program registry.
+//!
+//! The registry starts with the programs shipped with the CLI. A registry
+//! file can add new programs or override the built-in ones by name, e.g.:
+//!
+//! ```toml
+//! [[program]]
+//! name = "config-fork"
+//! program-id = "Config1111111111111111111111111111111111111"
+//! feature-id = "2Fr57nzzkLYXW695UdDxDeR5fhnZWSttZeZYemrnpGFV"
+//! buffer-address = "BuafH9fBv62u6XjzrzS4ZjAE8963ejqF5rt1f8Uga4Q3"
+//! fixtures-path = "instr/fixtures/config"
+//! repository = "https://github.com/solana-program/config.git"
+//!
+//! [program.cluster-buffer-addresses]
+//! devnet = "BuafH9fBv62u6XjzrzS4ZjAE8963ejqF5rt1f8Uga4Q3"
+//! ```
+
+use {
+    crate::program::{CustomProgramArgs, Program, ProgramEntry, CUSTOM_PROGRAM_NAME},
+    serde::Deserialize,
+    std::{collections::HashSet, path::Path},
+};
+
+/// The registry file loaded from the working directory, if present.
+pub const DEFAULT_REGISTRY_PATH: &str = "cbmt-programs.toml";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegistryFile {
+    #[serde(default)]
+    program: Vec<ProgramEntry>,
+}
+
+/// Registry of programs available for testing.
+pub struct Registry {
+    programs: Vec<Program>,
+}
+
+impl Registry {
+    /// Load the registry, merging the provided registry file (or the default
+    /// one, if it exists) over the built-in programs.
+    pub fn load(path: Option<&Path>) -> Result<Self, String> {
+        let mut registry = Self {
+            programs: Program::builtins(),
+        };
+
+        let path = match path {
+            Some(path) => Some(path),
+            None => Some(Path::new(DEFAULT_REGISTRY_PATH)).filter(|path| path.exists()),
+        };
+
+        if let Some(path) = path {
+            let contents = std::fs::read_to_string(path).map_err(|err| {
+                format!("Failed to read registry file {}: {}", path.display(), err)
+            })?;
+            registry.merge(&contents, path)?;
+        }
+
+        Ok(registry)
+    }
+
+    /// Merge the programs declared in the contents of a registry file over
+    /// the registered ones.
+    fn merge(&mut self, contents: &str, path: &Path) -> Result<(), String> {
+        let file: RegistryFile = toml::from_str(contents)
+            .map_err(|err| format!("Failed to parse registry file {}: {}", path.display(), err))?;
+
+        let mut seen = HashSet::new();
+        for entry in file.program {
+            let program = Program::try_from(entry)?;
+            if !seen.insert(program.name().to_string()) {
+                return Err(format!(
+                    "Program `{}` is declared more than once in {}",
+                    program.name(),
+                    path.display()
+                ));
+            }
+            self.insert(program);
+        }
+
+        Ok(())
+    }
+
+    fn insert(&mut self, program: Program) {
+        match self
+            .programs
+            .iter_mut()
+            .find(|existing| existing.name() == program.name())
+        {
+            Some(existing) => *existing = program,
+            None => self.programs.push(program),
+        }
+    }
+
+    /// Look up a program by name.
+    pub fn get(&self, name: &str) -> Result<Program, String> {
+        self.programs
+            .iter()
+            .find(|program| program.name() == name)
+            .cloned()
+            .ok_or_else(|| {
+                format!(
+                    "Invalid program name: {}. Registered programs: {}",
+                    name,
+                    self.programs
+                        .iter()
+                        .map(Program::name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+    }
+
+    /// All registered programs.
+    pub fn programs(&self) -> &[Program] {
+        &self.programs
+    }
+
+    /// Resolve the program to test, building an ad-hoc migration target from
+    /// the provided flags if the program name is `custom`.
+    pub fn resolve(&self, name: &str, custom: &CustomProgramArgs) -> Result<Program, String> {
+        self.resolve_all(&[name.to_string()], custom)
+            .map(|mut programs| programs.remove(0))
+    }
+
+    /// Resolve several programs to test together, building an ad-hoc
+    /// migration target from the provided flags for the `custom` program.
+    pub fn resolve_all(
+        &self,
+        names: &[String],
+        custom: &CustomProgramArgs,
+    ) -> Result<Vec<Program>, String> {
+        if !custom.is_empty() && !names.iter().any(|name| name == CUSTOM_PROGRAM_NAME) {
+            return Err(format!(
+                "Migration target flags can only be used with the `{}` program",
+                CUSTOM_PROGRAM_NAME
+            ));
+        }
+
+        let mut seen = HashSet::new();
+        names
+            .iter()
+            .map(|name| {
+                if !seen.insert(name) {
+                    return Err(format!("Program `{}` was provided more than once", name));
+                }
+                if name == CUSTOM_PROGRAM_NAME {
+                    custom.to_program()
+                } else {
+                    self.get(name)
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, solana_sdk::pubkey::Pubkey, std::str::FromStr};
+
+    const CONFIG_FORK: &str = r#"
+[[program]]
+name = "config-fork"
+program-id = "Config1111111111111111111111111111111111111"
+feature-id = "2Fr57nzzkLYXW695UdDxDeR5fhnZWSttZeZYemrnpGFV"
+buffer-address = "BuafH9fBv62u6XjzrzS4ZjAE8963ejqF5rt1f8Uga4Q3"
+"#;
+
+    const CONFIG_OVERRIDE: &str = r#"
+[[program]]
+name = "config"
+program-id = "Config1111111111111111111111111111111111111"
+feature-id = "2Fr57nzzkLYXW695UdDxDeR5fhnZWSttZeZYemrnpGFV"
+buffer-address = "BuafH9fBv62u6XjzrzS4ZjAE8963ejqF5rt1f8Uga4Q3"
+"#;
+
+    fn merged(contents: &str) -> Result<Registry, String> {
+        let mut registry = Registry {
+            programs: Program::builtins(),
+        };
+        registry.merge(contents, Path::new(DEFAULT_REGISTRY_PATH))?;
+        Ok(registry)
+    }
+
+    #[test]
+    fn test_merge_adds_new_program() {
+        let registry = merged(CONFIG_FORK).unwrap();
+
+        assert_eq!(registry.programs().len(), Program::builtins().len() + 1);
+        assert!(registry.get("config-fork").is_ok());
+    }
+
+    #[test]
+    fn test_merge_overrides_builtin_by_name() {
+        let registry = merged(CONFIG_OVERRIDE).unwrap();
+
+        assert_eq!(registry.programs().len(), Program::builtins().len());
+        assert_eq!(
+            registry.get("config").unwrap().buffer_address(),
+            Pubkey::from_str("BuafH9fBv62u6XjzrzS4ZjAE8963ejqF5rt1f8Uga4Q3").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_merge_rejects_duplicate_program() {
+        let contents = format!("{}{}", CONFIG_FORK, CONFIG_FORK);
+
+        let err = merged(&contents).err().unwrap();
+        assert!(err.contains("declared more than once"), "{}", err);
+    }
+
+    #[test]
+    fn test_merge_rejects_reserved_custom_name() {
+        let contents = CONFIG_FORK.replace("config-fork", CUSTOM_PROGRAM_NAME);
+
+        let err = merged(&contents).err().unwrap();
+        assert!(err.contains("reserved"), "{}", err);
+    }
+
+    #[test]
+    fn test_resolve_all_rejects_repeated_program() {
+        let registry = merged("").unwrap();
+        let names = vec!["config".to_string(), "config".to_string()];
+
+        let err = registry
+            .resolve_all(&names, &CustomProgramArgs::default())
+            .err()
+            .unwrap();
+        assert!(err.contains("more than once"), "{}", err);
+    }
+}
@@ -0,0 +1,339 @@
+//! Machine-readable test reports.
+//!
+//! Every stub check, fixture and conformance case is recorded as it is
+//! reported on the console. Once the command finishes, the recorded cases are
+//! written as a JSON document and/or a JUnit XML file.
+
+use {
+    crate::error::CbmtError,
+    clap::ValueEnum,
+    futures::FutureExt,
+    serde::Serialize,
+    std::{
+        fmt::Display,
+        future::Future,
+        panic::AssertUnwindSafe,
+        path::Path,
+        sync::Mutex,
+        time::{Duration, Instant},
+    },
+};
+
+const REPORT_FILE_NAME: &str = "cbmt-report";
+
+/// Fail the test, formatting the message as `panic!` does. The panic carries
+/// an [`AssertionFailure`], so it's told apart from the harness panicking.
+macro_rules! fail {
+    ($($arg:tt)+) => {
+        std::panic::panic_any($crate::report::AssertionFailure(format!($($arg)+)))
+    };
+}
+
+/// Fail the test unless the condition holds, as `assert!` does.
+macro_rules! ensure {
+    ($cond:expr $(,)?) => {
+        if !$cond {
+            $crate::report::fail!("assertion failed: {}", stringify!($cond))
+        }
+    };
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            $crate::report::fail!($($arg)+)
+        }
+    };
+}
+
+/// Fail the test unless both values are equal, as `assert_eq!` does.
+macro_rules! ensure_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if *left != *right {
+                    $crate::report::fail!(
+                        "assertion `left == right` failed\n  left: {:?}\n right: {:?}",
+                        left,
+                        right
+                    )
+                }
+            }
+        }
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if *left != *right {
+                    $crate::report::fail!(
+                        "{}\n  left: {:?}\n right: {:?}",
+                        format_args!($($arg)+),
+                        left,
+                        right
+                    )
+                }
+            }
+        }
+    };
+}
+
+pub(crate) use {ensure, ensure_eq, fail};
+
+/// The payload of a panic raised by a failed test assertion.
+pub struct AssertionFailure(pub String);
+
+static COLLECTOR: Mutex<Option<Collector>> = Mutex::new(None);
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ReportFormat {
+    /// A JSON document.
+    Json,
+    /// A JUnit XML file.
+    Junit,
+}
+
+/// The status of a single case, or of the whole run.
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Passed,
+    /// A test failed.
+    Failed,
+    /// The harness itself failed, so the test could not be run.
+    Errored,
+    /// The test was deliberately not run.
+    Skipped,
+}
+
+impl Status {
+    /// The process exit code for a run with this status.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::Passed => 0,
+            Self::Failed => 1,
+            Self::Errored => 2,
+            Self::Skipped => 0,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Case {
+    suite: String,
+    name: String,
+    status: Status,
+    duration_secs: f64,
+    diff: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    status: Status,
+    cases: &'a [Case],
+}
+
+struct Collector {
+    suite: String,
+    last: Instant,
+    cases: Vec<Case>,
+}
+
+/// Start recording cases for a suite.
+pub fn begin(suite: &str) {
+    *COLLECTOR.lock().unwrap() = Some(Collector {
+        suite: suite.to_string(),
+        last: Instant::now(),
+        cases: Vec::new(),
+    });
+}
+
+/// Record the result of a check. Its duration is the time since the previous
+/// case was recorded.
+pub fn record(name: impl Display, result: &Result<(), String>) {
+    let mut collector = COLLECTOR.lock().unwrap();
+    let Some(collector) = collector.as_mut() else {
+        return;
+    };
+    let duration = collector.last.elapsed();
+    let (status, diff) = match result {
+        Ok(()) => (Status::Passed, None),
+        Err(err) => (Status::Failed, Some(err.clone())),
+    };
+    collector.push(name.to_string(), status, diff, duration);
+}
+
+/// Record an error that stopped a check from running. Its duration is the
+/// time since the previous case was recorded.
+pub fn record_error(name: impl Display, err: String) {
+    let mut collector = COLLECTOR.lock().unwrap();
+    let Some(collector) = collector.as_mut() else {
+        return;
+    };
+    let duration = collector.last.elapsed();
+    collector.push(name.to_string(), Status::Errored, Some(err), duration);
+}
+
+/// Record a case with an explicit status and duration.
+pub fn record_case(name: impl Display, status: Status, diff: Option<String>, duration: Duration) {
+    if let Some(collector) = COLLECTOR.lock().unwrap().as_mut() {
+        collector.push(name.to_string(), status, diff, duration);
+    }
+}
+
+/// Print failed test assertions without a backtrace, leaving every other
+/// panic to the default hook.
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        match info.payload().downcast_ref::<AssertionFailure>() {
+            Some(failure) => eprintln!("Assertion failed: {}", failure.0),
+            None => default_hook(info),
+        }
+    }));
+}
+
+/// Run a test step, returning its failed assertion, if any, as an error so
+/// the steps after it still run. Any other panic is propagated.
+pub async fn catch_assertion(step: impl Future<Output = ()>) -> Result<(), String> {
+    match AssertUnwindSafe(step).catch_unwind().await {
+        Ok(()) => Ok(()),
+        Err(panic) => match panic.downcast::<AssertionFailure>() {
+            Ok(failure) => Err(failure.0),
+            Err(panic) => std::panic::resume_unwind(panic),
+        },
+    }
+}
+
+/// Whether any recorded case failed. Cases that errored are not failures.
+pub fn has_failures() -> bool {
+    COLLECTOR.lock().unwrap().as_ref().is_some_and(|collector| {
+        collector
+            .cases
+            .iter()
+            .any(|case| case.status == Status::Failed)
+    })
+}
+
+/// Write the recorded cases in each format, to the directory.
+pub fn write_reports(
+    formats: &[ReportFormat],
+    directory: &Path,
+    status: Status,
+) -> Result<(), CbmtError> {
+    let collector = COLLECTOR.lock().unwrap();
+    let cases = collector
+        .as_ref()
+        .map_or(&[][..], |collector| &collector.cases[..]);
+
+    std::fs::create_dir_all(directory).map_err(|err| {
+        CbmtError::Harness(format!(
+            "Failed to create report directory {}: {}",
+            directory.display(),
+            err
+        ))
+    })?;
+    for format in formats {
+        let (extension, contents) = match format {
+            ReportFormat::Json => (
+                "json",
+                serde_json::to_string_pretty(&Report { status, cases }).map_err(|err| {
+                    CbmtError::Harness(format!("Failed to serialize report: {}", err))
+                })?,
+            ),
+            ReportFormat::Junit => ("xml", junit(cases)),
+        };
+        let path = directory.join(format!("{}.{}", REPORT_FILE_NAME, extension));
+        std::fs::write(&path, contents).map_err(|err| {
+            CbmtError::Harness(format!(
+                "Failed to write report {}: {}",
+                path.display(),
+                err
+            ))
+        })?;
+        println!("Report written to {}", path.display());
+    }
+    Ok(())
+}
+
+impl Collector {
+    fn push(&mut self, name: String, status: Status, diff: Option<String>, duration: Duration) {
+        self.cases.push(Case {
+            suite: self.suite.clone(),
+            name,
+            status,
+            duration_secs: duration.as_secs_f64(),
+            diff,
+        });
+        self.last = Instant::now();
+    }
+}
+
+fn junit(cases: &[Case]) -> String {
+    let mut suites = Vec::<(&str, Vec<&Case>)>::new();
+    for case in cases {
+        match suites.iter_mut().find(|(suite, _)| *suite == case.suite) {
+            Some((_, suite_cases)) => suite_cases.push(case),
+            None => suites.push((case.suite.as_str(), vec![case])),
+        }
+    }
+
+    let count = |cases: &[&Case], status| cases.iter().filter(|case| case.status == status).count();
+    let time = |cases: &[&Case]| cases.iter().map(|case| case.duration_secs).sum::<f64>();
+
+    let all = cases.iter().collect::<Vec<_>>();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"cbmt\" tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n",
+        all.len(),
+        count(&all, Status::Failed),
+        count(&all, Status::Errored),
+        count(&all, Status::Skipped),
+        time(&all),
+    ));
+    for (suite, suite_cases) in &suites {
+        xml.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" \
+             time=\"{:.3}\">\n",
+            escape(suite),
+            suite_cases.len(),
+            count(suite_cases, Status::Failed),
+            count(suite_cases, Status::Errored),
+            count(suite_cases, Status::Skipped),
+            time(suite_cases),
+        ));
+        for case in suite_cases {
+            xml.push_str(&format!(
+                "    <testcase classname=\"cbmt.{}\" name=\"{}\" time=\"{:.3}\"",
+                escape(suite),
+                escape(&case.name),
+                case.duration_secs,
+            ));
+            let diff = case.diff.as_deref().unwrap_or_default();
+            match case.status {
+                Status::Passed => xml.push_str("/>\n"),
+                Status::Failed => xml.push_str(&format!(
+                    ">\n      <failure message=\"{}\">{}</failure>\n    </testcase>\n",
+                    escape(diff.lines().next().unwrap_or_default()),
+                    escape(diff),
+                )),
+                Status::Errored => xml.push_str(&format!(
+                    ">\n      <error message=\"{}\">{}</error>\n    </testcase>\n",
+                    escape(diff.lines().next().unwrap_or_default()),
+                    escape(diff),
+                )),
+                Status::Skipped => xml.push_str(&format!(
+                    ">\n      <skipped message=\"{}\"/>\n    </testcase>\n",
+                    escape(diff),
+                )),
+            }
+        }
+        xml.push_str("  </testsuite>\n");
+    }
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -0,0 +1,181 @@
+//! Verifiable build reproduction.
+//!
+//! Rebuilds a program from a local checkout of its repository using
+//! `solana-verify`'s deterministic (Docker-based) build, so the produced ELF
+//! can be compared against the buffer account contents.
+
+use {
+    crate::{
+        error::{command_output, run_command, CbmtError},
+        program::Program,
+        verify::{sha256_hex, trim_padding},
+    },
+    std::{
+        path::{Path, PathBuf},
+        process::Command,
+    },
+};
+
+/// A commit checked out in a repository, remembering what was checked out
+/// before so it can be restored.
+pub struct Checkout<'a> {
+    repo_path: &'a Path,
+    previous: String,
+}
+
+impl Checkout<'_> {
+    /// The branch, or commit if detached, checked out before.
+    pub fn previous(&self) -> &str {
+        &self.previous
+    }
+
+    /// Check out what was checked out before.
+    pub fn restore(self) -> Result<(), CbmtError> {
+        run_command(
+            git(self.repo_path).arg("checkout").arg(&self.previous),
+            CbmtError::Harness,
+        )
+    }
+}
+
+/// Check out the provided commit in the repository, refusing to proceed if
+/// the working tree has uncommitted changes or none of its remotes is the
+/// program's repository.
+pub fn checkout_commit<'a>(
+    repo_path: &'a Path,
+    commit: &str,
+    program: &Program,
+) -> Result<Checkout<'a>, CbmtError> {
+    if let Some(repository) = program.repository() {
+        let remotes = command_output(git(repo_path).arg("remote").arg("-v"), CbmtError::Harness)?;
+        if !remotes
+            .lines()
+            .filter_map(|line| line.split_whitespace().nth(1))
+            .any(|url| normalize_url(url) == normalize_url(repository))
+        {
+            return Err(CbmtError::Config(format!(
+                "Repository {} has no remote for {}",
+                repo_path.display(),
+                repository
+            )));
+        }
+    }
+
+    let status = command_output(
+        git(repo_path).arg("status").arg("--porcelain"),
+        CbmtError::Harness,
+    )?;
+    if !status.is_empty() {
+        return Err(CbmtError::Harness(format!(
+            "Repository {} has uncommitted changes",
+            repo_path.display()
+        )));
+    }
+
+    let previous = command_output(
+        git(repo_path)
+            .arg("symbolic-ref")
+            .arg("--short")
+            .arg("HEAD"),
+        CbmtError::Harness,
+    )
+    .or_else(|_| {
+        command_output(
+            git(repo_path).arg("rev-parse").arg("HEAD"),
+            CbmtError::Harness,
+        )
+    })?;
+
+    run_command(
+        git(repo_path).arg("checkout").arg("--detach").arg(commit),
+        CbmtError::Harness,
+    )?;
+
+    Ok(Checkout {
+        repo_path,
+        previous,
+    })
+}
+
+/// Run the deterministic build, returning the path to the produced ELF.
+pub fn verifiable_build(repo_path: &Path, library_name: &str) -> Result<PathBuf, CbmtError> {
+    run_command(
+        Command::new("solana-verify")
+            .current_dir(repo_path)
+            .arg("build")
+            .arg("--library-name")
+            .arg(library_name),
+        CbmtError::Build,
+    )?;
+
+    let elf_path = repo_path
+        .join("target")
+        .join("deploy")
+        .join(format!("{}.so", library_name));
+    if !elf_path.exists() {
+        return Err(CbmtError::Build(format!(
+            "Verifiable build did not produce {}",
+            elf_path.display()
+        )));
+    }
+
+    Ok(elf_path)
+}
+
+/// Compare the built ELF against the buffer ELF. Returns whether their
+/// hashes match.
+pub fn compare_build(built_elf: &[u8], buffer_elf: &[u8]) -> bool {
+    let built_hash = sha256_hex(trim_padding(built_elf));
+    let buffer_hash = sha256_hex(trim_padding(buffer_elf));
+
+    println!();
+    println!("    Build Hash    : {}", built_hash);
+    println!("    Buffer Hash   : {}", buffer_hash);
+    println!();
+
+    built_hash == buffer_hash
+}
+
+fn git(repo_path: &Path) -> Command {
+    let mut command = Command::new("git");
+    command.arg("-C").arg(repo_path);
+    command
+}
+
+/// Normalize a git remote URL, so SSH and HTTPS URLs of the same repository,
+/// with or without the `.git` suffix, compare equal.
+fn normalize_url(url: &str) -> String {
+    let url = url.trim_end_matches('/').trim_end_matches(".git");
+    let url = match url.strip_prefix("git@") {
+        Some(rest) => rest.replacen(':', "/", 1),
+        None => url
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .trim_start_matches("ssh://git@")
+            .to_string(),
+    };
+    url.to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_url() {
+        let expected = normalize_url("https://github.com/solana-program/config.git");
+
+        for url in [
+            "https://github.com/solana-program/config",
+            "https://github.com/solana-program/config/",
+            "git@github.com:solana-program/config.git",
+            "ssh://git@github.com/solana-program/config.git",
+        ] {
+            assert_eq!(normalize_url(url), expected, "{}", url);
+        }
+        assert_ne!(
+            normalize_url("https://github.com/someone/config.git"),
+            expected
+        );
+    }
+}
@@ -0,0 +1,201 @@
+//! Fixture skip lists.
+//!
+//! A program's fixtures that are known not to pass are listed in
+//! `skip-lists/<program>.toml`, each with the reason it is skipped:
+//!
+//! ```toml
+//! [[skip]]
+//! pattern = "<fixture hash>*"
+//! reason = "<why the fixture cannot pass>"
+//! ```
+//!
+//! Patterns are globs, matched against both the fixture's path relative to
+//! the fixtures directory and its file stem. Fixtures are filtered when they
+//! are run, so the fixtures directory is never modified.
+
+use {crate::program::Program, clap::Args, glob::Pattern, serde::Deserialize, std::path::Path};
+
+/// The directory containing each program's skip list.
+pub const SKIP_LIST_DIRECTORY: &str = "skip-lists";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SkipListFile {
+    #[serde(default)]
+    skip: Vec<SkipEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SkipEntry {
+    pattern: String,
+    reason: String,
+}
+
+/// Flags to narrow down the fixtures to run.
+#[derive(Args)]
+pub struct FixtureFilterArgs {
+    /// Skip fixtures matching this glob pattern, on top of the program's
+    /// skip list. May be repeated.
+    #[arg(long)]
+    pub skip: Vec<String>,
+    /// Only run fixtures matching this glob pattern. May be repeated.
+    #[arg(long)]
+    pub only: Vec<String>,
+}
+
+/// Decides which fixtures to run, and why the rest are skipped.
+pub struct FixtureFilter {
+    skip: Vec<(Pattern, String)>,
+    only: Vec<Pattern>,
+}
+
+impl FixtureFilter {
+    /// Load the program's skip list, if it has one, and apply the flags over
+    /// it.
+    pub fn load(program: &Program, args: &FixtureFilterArgs) -> Result<Self, String> {
+        let path = Path::new(SKIP_LIST_DIRECTORY).join(format!("{}.toml", program.name()));
+        let mut skip = if path.exists() {
+            read_skip_list(&path)?
+        } else {
+            Vec::new()
+        };
+
+        for pattern in &args.skip {
+            skip.push((parse_pattern(pattern)?, "Skipped with `--skip`".to_string()));
+        }
+
+        let only = args
+            .only
+            .iter()
+            .map(|pattern| parse_pattern(pattern))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { skip, only })
+    }
+
+    /// Why the fixture is skipped, if it is. The name is the fixture's path
+    /// relative to the fixtures directory.
+    pub fn skip_reason(&self, name: &Path) -> Option<String> {
+        let stem = name
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default();
+        let matches = |pattern: &Pattern| pattern.matches_path(name) || pattern.matches(&stem);
+
+        if let Some((_, reason)) = self.skip.iter().find(|(pattern, _)| matches(pattern)) {
+            return Some(reason.clone());
+        }
+        if !self.only.is_empty() && !self.only.iter().any(matches) {
+            return Some("Not matched by `--only`".to_string());
+        }
+        None
+    }
+}
+
+fn read_skip_list(path: &Path) -> Result<Vec<(Pattern, String)>, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read skip list {}: {}", path.display(), err))?;
+    let file: SkipListFile = toml::from_str(&contents)
+        .map_err(|err| format!("Failed to parse skip list {}: {}", path.display(), err))?;
+    file.skip
+        .into_iter()
+        .map(|entry| {
+            if entry.reason.trim().is_empty() {
+                return Err(format!(
+                    "Skip list {} has no reason for `{}`",
+                    path.display(),
+                    entry.pattern
+                ));
+            }
+            Ok((parse_pattern(&entry.pattern)?, entry.reason))
+        })
+        .collect()
+}
+
+fn parse_pattern(pattern: &str) -> Result<Pattern, String> {
+    Pattern::new(pattern).map_err(|err| format!("Invalid fixture pattern `{}`: {}", pattern, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(skip: &[&str], only: &[&str]) -> FixtureFilter {
+        FixtureFilter {
+            skip: skip
+                .iter()
+                .map(|pattern| (parse_pattern(pattern).unwrap(), format!("skip {}", pattern)))
+                .collect(),
+            only: only
+                .iter()
+                .map(|pattern| parse_pattern(pattern).unwrap())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_skip_matches_file_stem() {
+        let filter = filter(&["abc123*"], &[]);
+
+        assert_eq!(
+            filter.skip_reason(Path::new("nested/abc123_456.fix")),
+            Some("skip abc123*".to_string())
+        );
+        assert_eq!(filter.skip_reason(Path::new("nested/def456.fix")), None);
+    }
+
+    #[test]
+    fn test_skip_matches_path() {
+        let filter = filter(&["nested/*"], &[]);
+
+        assert_eq!(
+            filter.skip_reason(Path::new("nested/abc123.fix")),
+            Some("skip nested/*".to_string())
+        );
+        assert_eq!(filter.skip_reason(Path::new("other/abc123.fix")), None);
+    }
+
+    #[test]
+    fn test_only_skips_unmatched_fixtures() {
+        let filter = filter(&[], &["abc*"]);
+
+        assert_eq!(filter.skip_reason(Path::new("abc123.fix")), None);
+        assert_eq!(
+            filter.skip_reason(Path::new("def456.fix")),
+            Some("Not matched by `--only`".to_string())
+        );
+    }
+
+    #[test]
+    fn test_skip_lists_match_fixture_files() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join(SKIP_LIST_DIRECTORY);
+        for program in Program::builtins() {
+            let path = directory.join(format!("{}.toml", program.name()));
+            if !path.exists() {
+                continue;
+            }
+            for (pattern, _) in read_skip_list(&path).unwrap() {
+                let hash = pattern.as_str().trim_end_matches('*');
+                assert!(
+                    pattern.matches_path(Path::new(&format!("{}.fix", hash))),
+                    "`{}` in {} does not match a fixture file",
+                    pattern,
+                    path.display()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_skip_takes_precedence_over_only() {
+        let filter = filter(&["abc123"], &["abc*"]);
+
+        assert_eq!(
+            filter.skip_reason(Path::new("abc123.fix")),
+            Some("skip abc123".to_string())
+        );
+    }
+}
@@ -0,0 +1,227 @@
+//! Smoke suites for the real programs.
+//!
+//! When the buffer is populated with the real program ELF, the stub tests
+//! cannot be used. Instead, each builtin has a short suite of real
+//! instructions to ensure the migrated program behaves as expected.
+
+use {
+    crate::{
+        report::{ensure, ensure_eq},
+        validator::ValidatorContext,
+    },
+    serde::Serialize,
+    solana_config_program::{config_instruction, get_config_data, ConfigKeys, ConfigState},
+    solana_sdk::{
+        address_lookup_table::{self, instruction as alt_instruction, state::AddressLookupTable},
+        commitment_config::CommitmentConfig,
+        feature::{self, Feature},
+        incinerator,
+        instruction::{AccountMeta, Instruction},
+        pubkey::Pubkey,
+        rent::Rent,
+        signature::{Keypair, Signature},
+        signer::Signer,
+        system_instruction, system_program,
+    },
+};
+
+/// The `RevokePendingActivation` instruction of the Core BPF Feature Gate
+/// program.
+const REVOKE_PENDING_ACTIVATION_TAG: u8 = 0;
+
+/// Config account data stored by the Config smoke suite.
+#[derive(Default, Serialize)]
+pub struct SmokeConfig {
+    pub value: [u8; 32],
+}
+
+impl ConfigState for SmokeConfig {
+    fn max_space() -> u64 {
+        32
+    }
+}
+
+/// Whether a smoke suite exists for the program.
+pub fn has_smoke_suite(program_id: &Pubkey) -> bool {
+    *program_id == address_lookup_table::program::id()
+        || *program_id == solana_sdk::config::program::id()
+        || *program_id == feature::id()
+}
+
+impl ValidatorContext {
+    /// Run the program's smoke suite, returning the signature of each step
+    /// by name.
+    pub async fn run_smoke_tests(&self, program_id: &Pubkey) -> Vec<(&'static str, Signature)> {
+        if *program_id == address_lookup_table::program::id() {
+            self.run_smoke_test_address_lookup_table().await
+        } else if *program_id == solana_sdk::config::program::id() {
+            self.run_smoke_test_config().await
+        } else if *program_id == feature::id() {
+            self.run_smoke_test_feature_gate().await
+        } else {
+            panic!("No smoke suite for program {}", program_id)
+        }
+    }
+
+    async fn run_smoke_test_address_lookup_table(&self) -> Vec<(&'static str, Signature)> {
+        let authority = &self.payer;
+        let recent_slot = self
+            .test_validator
+            .get_async_rpc_client()
+            .get_slot_with_commitment(CommitmentConfig::finalized())
+            .await
+            .unwrap();
+
+        let (create_ix, table_address) = alt_instruction::create_lookup_table(
+            authority.pubkey(),
+            self.payer.pubkey(),
+            recent_slot,
+        );
+        let create = self
+            .send_transaction(&[create_ix], &self.payer.pubkey(), &[&self.payer])
+            .await;
+
+        let (table_authority, addresses) = self.get_lookup_table(&table_address).await;
+        ensure_eq!(table_authority, Some(authority.pubkey()));
+        ensure!(addresses.is_empty());
+
+        let new_addresses = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let extend = self
+            .send_transaction(
+                &[alt_instruction::extend_lookup_table(
+                    table_address,
+                    authority.pubkey(),
+                    Some(self.payer.pubkey()),
+                    new_addresses.clone(),
+                )],
+                &self.payer.pubkey(),
+                &[&self.payer],
+            )
+            .await;
+
+        let (_, addresses) = self.get_lookup_table(&table_address).await;
+        ensure_eq!(addresses, new_addresses);
+
+        let freeze = self
+            .send_transaction(
+                &[alt_instruction::freeze_lookup_table(
+                    table_address,
+                    authority.pubkey(),
+                )],
+                &self.payer.pubkey(),
+                &[&self.payer],
+            )
+            .await;
+
+        let (table_authority, addresses) = self.get_lookup_table(&table_address).await;
+        ensure_eq!(table_authority, None);
+        ensure_eq!(addresses, new_addresses);
+
+        vec![
+            ("create_lookup_table", create),
+            ("extend_lookup_table", extend),
+            ("freeze_lookup_table", freeze),
+        ]
+    }
+
+    async fn run_smoke_test_config(&self) -> Vec<(&'static str, Signature)> {
+        let config = Keypair::new();
+        let keys = vec![(config.pubkey(), true)];
+        let space = SmokeConfig::max_space() + ConfigKeys::serialized_size(keys.clone());
+        let lamports = Rent::default().minimum_balance(space as usize);
+
+        let create = self
+            .send_transaction(
+                &config_instruction::create_account::<SmokeConfig>(
+                    &self.payer.pubkey(),
+                    &config.pubkey(),
+                    lamports,
+                    keys.clone(),
+                ),
+                &self.payer.pubkey(),
+                &[&self.payer, &config],
+            )
+            .await;
+
+        let data = SmokeConfig {
+            value: Pubkey::new_unique().to_bytes(),
+        };
+        let store = self
+            .send_transaction(
+                &[config_instruction::store(
+                    &config.pubkey(),
+                    /* is_config_signer */ true,
+                    keys,
+                    &data,
+                )],
+                &self.payer.pubkey(),
+                &[&self.payer, &config],
+            )
+            .await;
+
+        let config_account = self.get_account(&config.pubkey()).await.unwrap();
+        ensure_eq!(config_account.owner, solana_sdk::config::program::id());
+        let stored = get_config_data(&config_account.data).unwrap();
+        ensure_eq!(&stored[..32], &data.value);
+
+        vec![("create_config_account", create), ("store_config", store)]
+    }
+
+    async fn run_smoke_test_feature_gate(&self) -> Vec<(&'static str, Signature)> {
+        let pending_feature = Keypair::new();
+        let space = Feature::size_of();
+        let lamports = Rent::default().minimum_balance(space);
+
+        let create = self
+            .send_transaction(
+                &[system_instruction::create_account(
+                    &self.payer.pubkey(),
+                    &pending_feature.pubkey(),
+                    lamports,
+                    space as u64,
+                    &feature::id(),
+                )],
+                &self.payer.pubkey(),
+                &[&self.payer, &pending_feature],
+            )
+            .await;
+
+        let feature_account = self.get_account(&pending_feature.pubkey()).await.unwrap();
+        ensure_eq!(feature_account.owner, feature::id());
+
+        let revoke = self
+            .send_transaction(
+                &[revoke_pending_activation(&pending_feature.pubkey())],
+                &self.payer.pubkey(),
+                &[&self.payer, &pending_feature],
+            )
+            .await;
+
+        ensure!(self.get_account(&pending_feature.pubkey()).await.is_none());
+
+        vec![
+            ("create_pending_feature", create),
+            ("revoke_pending_activation", revoke),
+        ]
+    }
+
+    /// Get a lookup table's authority and addresses.
+    pub async fn get_lookup_table(&self, table_address: &Pubkey) -> (Option<Pubkey>, Vec<Pubkey>) {
+        let account = self.get_account(table_address).await.unwrap();
+        ensure_eq!(account.owner, address_lookup_table::program::id());
+        let table = AddressLookupTable::deserialize(&account.data).unwrap();
+        (table.meta.authority, table.addresses.to_vec())
+    }
+}
+
+pub fn revoke_pending_activation(feature_id: &Pubkey) -> Instruction {
+    Instruction::new_with_bytes(
+        feature::id(),
+        &[REVOKE_PENDING_ACTIVATION_TAG],
+        vec![
+            AccountMeta::new(*feature_id, true),
+            AccountMeta::new(incinerator::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
@@ -0,0 +1,255 @@
+//! Pre-existing state preservation.
+//!
+//! Accounts written by a builtin before its migration must be readable and
+//! mutable by the migrated program. These checks create such accounts while
+//! the builtin is still in place, snapshot them, and after the migration
+//! ensure they are unchanged and still fully usable.
+
+use {
+    crate::{
+        report::{ensure, ensure_eq, fail},
+        smoke::{revoke_pending_activation, SmokeConfig},
+        validator::ValidatorContext,
+    },
+    solana_config_program::{config_instruction, get_config_data, ConfigKeys, ConfigState},
+    solana_sdk::{
+        account::Account,
+        address_lookup_table::{self, instruction as alt_instruction},
+        commitment_config::CommitmentConfig,
+        feature::{self, Feature},
+        pubkey::Pubkey,
+        rent::Rent,
+        signature::Keypair,
+        signer::Signer,
+        system_instruction,
+    },
+};
+
+/// Ensure an account's owner, lamports, executable flag and data are
+/// unchanged, naming the first one that changed.
+pub fn check_account_unchanged(before: &Account, after: &Account) -> Result<(), String> {
+    // The rent epoch is runtime bookkeeping, not program state.
+    if after.owner != before.owner {
+        return Err(format!(
+            "owner changed from {} to {}",
+            before.owner, after.owner
+        ));
+    }
+    if after.lamports != before.lamports {
+        return Err(format!(
+            "lamports changed from {} to {}",
+            before.lamports, after.lamports
+        ));
+    }
+    if after.executable != before.executable {
+        return Err(format!(
+            "executable flag changed from {} to {}",
+            before.executable, after.executable
+        ));
+    }
+    if after.data != before.data {
+        return Err("data changed".to_string());
+    }
+    Ok(())
+}
+
+/// Accounts created through a builtin before its migration.
+pub enum PreexistingState {
+    /// An unfrozen lookup table with two addresses, owned by the payer.
+    AddressLookupTable { table_address: Pubkey },
+    /// A config account signed by its own keypair.
+    Config { config: Keypair },
+    /// A pending feature account.
+    FeatureGate { pending_feature: Keypair },
+}
+
+impl PreexistingState {
+    fn addresses(&self) -> Vec<Pubkey> {
+        match self {
+            Self::AddressLookupTable { table_address } => vec![*table_address],
+            Self::Config { config } => vec![config.pubkey()],
+            Self::FeatureGate { pending_feature } => vec![pending_feature.pubkey()],
+        }
+    }
+}
+
+/// The state created for a program, along with a snapshot of its accounts.
+pub struct StateSnapshot {
+    state: PreexistingState,
+    accounts: Vec<(Pubkey, Account)>,
+}
+
+impl ValidatorContext {
+    /// Create accounts owned by the builtin, and snapshot them.
+    pub async fn create_preexisting_state(&self, program_id: &Pubkey) -> StateSnapshot {
+        let state = if *program_id == address_lookup_table::program::id() {
+            self.create_preexisting_lookup_table().await
+        } else if *program_id == solana_sdk::config::program::id() {
+            self.create_preexisting_config().await
+        } else if *program_id == feature::id() {
+            self.create_preexisting_pending_feature().await
+        } else {
+            panic!("No pre-existing state for program {}", program_id)
+        };
+
+        let mut accounts = Vec::new();
+        for address in state.addresses() {
+            let account = self.get_account(&address).await.unwrap();
+            accounts.push((address, account));
+        }
+
+        StateSnapshot { state, accounts }
+    }
+
+    /// Ensure the snapshotted accounts are unchanged, then mutate them through
+    /// the migrated program.
+    pub async fn verify_preexisting_state(&self, snapshot: &StateSnapshot) {
+        for (address, before) in &snapshot.accounts {
+            let after = self.get_account(address).await.unwrap();
+            if let Err(err) = check_account_unchanged(before, &after) {
+                fail!("Account {}: {}", address, err);
+            }
+        }
+
+        match &snapshot.state {
+            PreexistingState::AddressLookupTable { table_address } => {
+                self.use_preexisting_lookup_table(table_address).await
+            }
+            PreexistingState::Config { config } => self.use_preexisting_config(config).await,
+            PreexistingState::FeatureGate { pending_feature } => {
+                self.use_preexisting_pending_feature(pending_feature).await
+            }
+        }
+    }
+
+    async fn create_preexisting_lookup_table(&self) -> PreexistingState {
+        let recent_slot = self
+            .test_validator
+            .get_async_rpc_client()
+            .get_slot_with_commitment(CommitmentConfig::finalized())
+            .await
+            .unwrap();
+        let (create_ix, table_address) = alt_instruction::create_lookup_table(
+            self.payer.pubkey(),
+            self.payer.pubkey(),
+            recent_slot,
+        );
+        let extend_ix = alt_instruction::extend_lookup_table(
+            table_address,
+            self.payer.pubkey(),
+            Some(self.payer.pubkey()),
+            vec![Pubkey::new_unique(), Pubkey::new_unique()],
+        );
+        self.send_transaction(
+            &[create_ix, extend_ix],
+            &self.payer.pubkey(),
+            &[&self.payer],
+        )
+        .await;
+
+        PreexistingState::AddressLookupTable { table_address }
+    }
+
+    async fn use_preexisting_lookup_table(&self, table_address: &Pubkey) {
+        let (_, before) = self.get_lookup_table(table_address).await;
+
+        let new_address = Pubkey::new_unique();
+        self.send_transaction(
+            &[
+                alt_instruction::extend_lookup_table(
+                    *table_address,
+                    self.payer.pubkey(),
+                    Some(self.payer.pubkey()),
+                    vec![new_address],
+                ),
+                alt_instruction::freeze_lookup_table(*table_address, self.payer.pubkey()),
+            ],
+            &self.payer.pubkey(),
+            &[&self.payer],
+        )
+        .await;
+
+        let (authority, after) = self.get_lookup_table(table_address).await;
+        ensure_eq!(authority, None);
+        ensure_eq!(after[..before.len()], before[..]);
+        ensure_eq!(after[before.len()..], [new_address]);
+    }
+
+    async fn create_preexisting_config(&self) -> PreexistingState {
+        let config = Keypair::new();
+        let keys = vec![(config.pubkey(), true)];
+        let space = SmokeConfig::max_space() + ConfigKeys::serialized_size(keys.clone());
+        let lamports = Rent::default().minimum_balance(space as usize);
+
+        let mut instructions = config_instruction::create_account::<SmokeConfig>(
+            &self.payer.pubkey(),
+            &config.pubkey(),
+            lamports,
+            keys.clone(),
+        );
+        instructions.push(config_instruction::store(
+            &config.pubkey(),
+            /* is_config_signer */ true,
+            keys,
+            &SmokeConfig {
+                value: Pubkey::new_unique().to_bytes(),
+            },
+        ));
+        self.send_transaction(&instructions, &self.payer.pubkey(), &[&self.payer, &config])
+            .await;
+
+        PreexistingState::Config { config }
+    }
+
+    async fn use_preexisting_config(&self, config: &Keypair) {
+        let data = SmokeConfig {
+            value: Pubkey::new_unique().to_bytes(),
+        };
+        self.send_transaction(
+            &[config_instruction::store(
+                &config.pubkey(),
+                /* is_config_signer */ true,
+                vec![(config.pubkey(), true)],
+                &data,
+            )],
+            &self.payer.pubkey(),
+            &[&self.payer, config],
+        )
+        .await;
+
+        let config_account = self.get_account(&config.pubkey()).await.unwrap();
+        let stored = get_config_data(&config_account.data).unwrap();
+        ensure_eq!(&stored[..32], &data.value);
+    }
+
+    async fn create_preexisting_pending_feature(&self) -> PreexistingState {
+        let pending_feature = Keypair::new();
+        let space = Feature::size_of();
+        let lamports = Rent::default().minimum_balance(space);
+        self.send_transaction(
+            &[system_instruction::create_account(
+                &self.payer.pubkey(),
+                &pending_feature.pubkey(),
+                lamports,
+                space as u64,
+                &feature::id(),
+            )],
+            &self.payer.pubkey(),
+            &[&self.payer, &pending_feature],
+        )
+        .await;
+
+        PreexistingState::FeatureGate { pending_feature }
+    }
+
+    async fn use_preexisting_pending_feature(&self, pending_feature: &Keypair) {
+        self.send_transaction(
+            &[revoke_pending_activation(&pending_feature.pubkey())],
+            &self.payer.pubkey(),
+            &[&self.payer, pending_feature],
+        )
+        .await;
+
+        ensure!(self.get_account(&pending_feature.pubkey()).await.is_none());
+    }
+}
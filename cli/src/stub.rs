@@ -0,0 +1,470 @@
+//! Stub test flow.
+//!
+//! Stages the buffer and feature accounts for every program in a single test
+//! validator, activates the features, and checks each program is migrated
+//! and invokable.
+//!
+//! By default, every buffer holds the stub program's ELF. With an ELF source,
+//! the buffers hold the real program ELFs, and each program's smoke suite is
+//! run instead of the stub tests.
+
+use {
+    crate::{
+        accounting::AccountingSnapshot,
+        elf::ElfSource,
+        error::{run_command, CbmtError},
+        output::{output, title_stub_test},
+        program::Program,
+        report::{self, ensure},
+        smoke::has_smoke_suite,
+        validator::{report_checks, FailureMode, MigrationTarget, ValidatorArgs, ValidatorContext},
+        write_elf_to_file, ELF_DIRECTORY,
+    },
+    std::{collections::HashSet, process::Command},
+};
+
+pub const MANIFEST_PATH_ACTIVATOR: &str = "./programs/activator/Cargo.toml";
+const MANIFEST_PATH_STUB: &str = "./programs/stub/Cargo.toml";
+
+const STUB_ELF_NAME: &str = "cbmt_program_stub";
+
+/// Options for a stub test run.
+pub struct StubOptions {
+    pub validator: ValidatorArgs,
+    /// Activate each feature in its own epoch, rather than all at once.
+    pub stagger: bool,
+    /// Where to load the real program ELFs from. The stub program's ELF is
+    /// used if not provided.
+    pub source: Option<ElfSource>,
+    /// Create accounts through each builtin before migration, and check they
+    /// are preserved and usable afterwards. Requires the real program ELFs.
+    pub preserve_state: bool,
+    /// Stage malformed buffers, each in a fresh test validator, and check the
+    /// migration is skipped.
+    pub failure_modes: Vec<FailureMode>,
+    /// Check the change in total supply is accounted for by the lamports
+    /// each migration burns and funds.
+    pub accounting: bool,
+    /// Probe the programs in the slots around the migration, checking when
+    /// each migrated program becomes visible. Requires the stub program.
+    pub timing_matrix: bool,
+    /// Activate the features and revoke them before the epoch boundary,
+    /// checking nothing is migrated, before activating them for real.
+    pub revoke_first: bool,
+}
+
+pub async fn run_stub_test(programs: &[Program], options: &StubOptions) -> Result<(), CbmtError> {
+    check_targets_are_distinct(programs).map_err(CbmtError::Config)?;
+
+    if options.revoke_first
+        && programs
+            .iter()
+            .any(|program| program.program_id() == solana_sdk::feature::id())
+    {
+        return Err(CbmtError::Config(
+            "Activations are revoked through a stand-in deployed at the Feature Gate program's \
+             address, so the Feature Gate program cannot be tested"
+                .to_string(),
+        ));
+    }
+
+    if options.preserve_state && options.source.is_none() {
+        return Err(CbmtError::Config(
+            "State preservation can only be tested with the real program ELFs".to_string(),
+        ));
+    }
+
+    if let Some(ElfSource::Elf(_) | ElfSource::BufferJson(_)) = &options.source {
+        if programs.len() > 1 {
+            return Err(CbmtError::Config(
+                "A local ELF can only be used to test a single program".to_string(),
+            ));
+        }
+    }
+
+    title_stub_test(
+        &programs
+            .iter()
+            .map(|program| (program.feature_gate(), program.buffer_address()))
+            .collect::<Vec<_>>(),
+        &options
+            .source
+            .as_ref()
+            .map_or("Stub program".to_string(), ToString::to_string),
+    );
+
+    output("Bulding programs...");
+    cargo_build_sbf(MANIFEST_PATH_ACTIVATOR)?;
+
+    let elf_names = match &options.source {
+        Some(source) => {
+            let mut elf_names = Vec::new();
+            for program in programs {
+                output(&format!("Loading ELF for {} from {}...", program, source));
+                let elf = source.load(program).await?;
+                write_elf_to_file(elf, &program.elf_name())?;
+                elf_names.push(program.elf_stem());
+            }
+            elf_names
+        }
+        None => {
+            cargo_build_sbf(MANIFEST_PATH_STUB)?;
+            vec![STUB_ELF_NAME.to_string(); programs.len()]
+        }
+    };
+
+    if !options.failure_modes.is_empty() {
+        for failure_mode in &options.failure_modes {
+            run_failure_mode(programs, &elf_names, *failure_mode, options).await;
+        }
+        output("Test complete! Woohoo!");
+        return Ok(());
+    }
+
+    output("Starting test validator...");
+    let context = start_validator(programs, &elf_names, None, options).await;
+
+    for program in programs {
+        output(&format!(
+            "Checking to see if {} is currently a builtin...",
+            program
+        ));
+        assert_program_is_builtin(&context, program).await;
+        output("It is.");
+    }
+
+    let mut snapshots = Vec::new();
+    if options.preserve_state {
+        for program in programs {
+            if !has_smoke_suite(&program.program_id()) {
+                output(&format!("No pre-existing state for {}, skipping.", program));
+                continue;
+            }
+            output(&format!("Creating accounts owned by {}...", program));
+            let snapshot = context
+                .create_preexisting_state(&program.program_id())
+                .await;
+            snapshots.push((program, snapshot));
+        }
+    }
+
+    if options.revoke_first {
+        activate_features(&context, programs).await;
+
+        for program in programs {
+            output(&format!(
+                "Revoking feature {} for {}...",
+                program.feature_gate(),
+                program
+            ));
+            context
+                .revoke_pending_activation(&program.feature_gate())
+                .await;
+        }
+
+        context.wait_for_next_epoch().await;
+        context.wait_for_next_slot().await;
+
+        for program in programs {
+            output(&format!(
+                "Checking to see if {} is still a builtin...",
+                program
+            ));
+            assert_program_is_builtin(&context, program).await;
+            ensure!(
+                context
+                    .get_account(&program.buffer_address())
+                    .await
+                    .is_some(),
+                "Buffer account was consumed"
+            );
+            output("It is.");
+        }
+    }
+
+    if options.accounting {
+        // The first epoch boundary carries one-off bookkeeping of its own, so
+        // the migrations are pushed past it.
+        output("Waiting for the first epoch boundary before activating...");
+        context.wait_for_next_epoch().await;
+    }
+
+    if options.stagger {
+        for (i, program) in programs.iter().enumerate() {
+            output(&format!(
+                "Activating feature {} for {}...",
+                program.feature_gate(),
+                program
+            ));
+            context
+                .activate_feature_with_payload(&program.feature_gate())
+                .await;
+
+            let accounting = snapshot_accounting(&context, &programs[i..=i], options).await;
+
+            context.wait_for_next_epoch().await;
+
+            assert_programs_are_bpf(&context, &programs[..=i]).await;
+            assert_accounting(&context, accounting).await;
+
+            for pending in &programs[i + 1..] {
+                output(&format!(
+                    "Checking to see if {} is still a builtin...",
+                    pending
+                ));
+                assert_program_is_builtin(&context, pending).await;
+                output("It is.");
+            }
+        }
+    } else {
+        activate_features(&context, programs).await;
+
+        let accounting = snapshot_accounting(&context, programs, options).await;
+
+        if options.timing_matrix {
+            output("Probing programs across the epoch boundary...");
+            context.run_timing_matrix(programs).await;
+            output("Every probe reached the expected program.");
+        } else {
+            context.wait_for_next_epoch().await;
+        }
+
+        assert_programs_are_bpf(&context, programs).await;
+        assert_accounting(&context, accounting).await;
+    }
+
+    let real_elf = options.source.is_some();
+
+    context.wait_for_next_slot().await;
+
+    for (program, snapshot) in &snapshots {
+        output(&format!(
+            "Checking accounts created by {} are preserved and usable...",
+            program
+        ));
+        context.verify_preexisting_state(snapshot).await;
+        output("They are.");
+    }
+
+    for program in programs {
+        output(&format!(
+            "Checking nobody can upgrade, reassign or close {}...",
+            program
+        ));
+        context
+            .assert_program_is_immutable(&program.program_id(), &program.buffer_address())
+            .await;
+        output("Nobody can.");
+    }
+
+    for program in programs {
+        run_tests(&context, program, real_elf, "").await;
+    }
+
+    context.wait_for_next_epoch().await;
+
+    for program in programs {
+        run_tests(&context, program, real_elf, " again").await;
+    }
+
+    output("Test complete! Woohoo!");
+
+    Ok(())
+}
+
+/// Activate every program's feature with a single instruction.
+async fn activate_features(context: &ValidatorContext, programs: &[Program]) {
+    for program in programs {
+        output(&format!(
+            "Activating feature {} for {}...",
+            program.feature_gate(),
+            program
+        ));
+    }
+    context
+        .activate_features(
+            &programs
+                .iter()
+                .map(|program| program.feature_gate())
+                .collect::<Vec<_>>(),
+        )
+        .await;
+}
+
+async fn snapshot_accounting(
+    context: &ValidatorContext,
+    programs: &[Program],
+    options: &StubOptions,
+) -> Option<AccountingSnapshot> {
+    if !options.accounting {
+        return None;
+    }
+    output("Recording balances and total supply...");
+    Some(context.snapshot_accounting(programs).await)
+}
+
+async fn assert_accounting(context: &ValidatorContext, snapshot: Option<AccountingSnapshot>) {
+    if let Some(snapshot) = snapshot {
+        output("Checking lamports are fully accounted for...");
+        context.assert_accounting(&snapshot).await;
+        output("They are.");
+    }
+}
+
+/// Stage every program's buffer malformed according to the failure mode,
+/// activate the features, and check nothing was migrated.
+async fn run_failure_mode(
+    programs: &[Program],
+    elf_names: &[String],
+    failure_mode: FailureMode,
+    options: &StubOptions,
+) {
+    output(&format!(
+        "Starting test validator with failure mode {}...",
+        failure_mode
+    ));
+    let context = start_validator(programs, elf_names, Some(failure_mode), options).await;
+
+    activate_features(&context, programs).await;
+
+    context.wait_for_next_epoch().await;
+    context.wait_for_next_slot().await;
+
+    for program in programs {
+        output(&format!(
+            "Checking to see if {} is still a builtin...",
+            program
+        ));
+        assert_program_is_builtin(&context, program).await;
+        output("It is.");
+
+        if failure_mode.buffer_exists() {
+            output("Checking to see if the buffer was left in place...");
+            ensure!(
+                context
+                    .get_account(&program.buffer_address())
+                    .await
+                    .is_some(),
+                "Buffer account was consumed"
+            );
+            output("It was.");
+        }
+    }
+
+    output("Checking to see if the validator is still producing blocks...");
+    context.assert_validator_is_producing_blocks().await;
+    output("It is.");
+}
+
+async fn start_validator(
+    programs: &[Program],
+    elf_names: &[String],
+    failure_mode: Option<FailureMode>,
+    options: &StubOptions,
+) -> ValidatorContext {
+    ValidatorContext::start(
+        &programs
+            .iter()
+            .zip(elf_names)
+            .map(|(program, elf_name)| MigrationTarget {
+                feature_id: program.feature_gate(),
+                buffer_address: program.buffer_address(),
+                elf_name,
+                failure_mode,
+            })
+            .collect::<Vec<_>>(),
+        ELF_DIRECTORY,
+        &options.validator,
+        options.revoke_first,
+        options.accounting,
+    )
+    .await
+}
+
+/// Run the stub tests, or the program's smoke suite when testing the real
+/// program ELF.
+async fn run_tests(context: &ValidatorContext, program: &Program, real_elf: bool, again: &str) {
+    if !real_elf {
+        output(&format!("Running stub tests{} on {}...", again, program));
+        let checks = context
+            .run_stub_tests(&program.program_id())
+            .await
+            .into_iter()
+            .map(|(step, result)| (format!("{}: stub {}{}", program, step, again), result))
+            .collect::<Vec<_>>();
+        ensure!(report_checks(&checks), "Stub tests failed on {}", program);
+    } else if has_smoke_suite(&program.program_id()) {
+        output(&format!("Running smoke tests{} on {}...", again, program));
+        context.run_smoke_tests(&program.program_id()).await;
+        report::record(format!("{}: Smoke tests{}", program, again), &Ok(()));
+    } else {
+        output(&format!("No smoke suite for {}, skipping.", program));
+        return;
+    }
+    output("Success.");
+}
+
+async fn assert_program_is_builtin(context: &ValidatorContext, program: &Program) {
+    // The Feature Gate program has no program account before migration.
+    if program.program_id() == solana_sdk::feature::id() {
+        ensure!(
+            context.get_account(&program.program_id()).await.is_none(),
+            "Feature Gate program is not a builtin"
+        );
+    } else {
+        context
+            .assert_program_is_builtin(&program.program_id())
+            .await;
+    }
+}
+
+async fn assert_programs_are_bpf(context: &ValidatorContext, programs: &[Program]) {
+    for program in programs {
+        output(&format!(
+            "Checking to see if {} is now a BPF program...",
+            program
+        ));
+        context
+            .assert_program_is_bpf(
+                &program.program_id(),
+                &program.feature_gate(),
+                &program.buffer_address(),
+            )
+            .await;
+        output("It is.");
+    }
+}
+
+/// Programs migrated in the same validator cannot share any accounts.
+fn check_targets_are_distinct(programs: &[Program]) -> Result<(), String> {
+    let mut seen = HashSet::new();
+    for program in programs {
+        for address in [
+            program.program_id(),
+            program.feature_gate(),
+            program.buffer_address(),
+        ] {
+            if !seen.insert(address) {
+                return Err(format!(
+                    "Program `{}` shares address {} with another program under test",
+                    program, address
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn cargo_build_sbf(manifest_path: &str) -> Result<(), CbmtError> {
+    run_command(
+        Command::new("cargo")
+            .arg("build-sbf")
+            .arg("--manifest-path")
+            .arg(manifest_path)
+            .arg("--features")
+            .arg("sbf-entrypoint")
+            .arg("--sbf-out-dir")
+            .arg(ELF_DIRECTORY),
+        CbmtError::Build,
+    )
+}
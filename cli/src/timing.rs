@@ -0,0 +1,208 @@
+//! Activation timing matrix.
+//!
+//! The migration happens in the first slot of the new epoch, but the program
+//! cache only makes the new program visible from the following slot. Probes
+//! are sent continuously across the epoch boundary, and the slot each one
+//! landed in determines whether it should have reached the builtin or the
+//! migrated program.
+
+use {
+    crate::{
+        program::Program,
+        report::ensure,
+        validator::{report_checks, ValidatorContext},
+    },
+    solana_rpc_client_api::config::RpcSendTransactionConfig,
+    solana_sdk::{
+        clock::Slot,
+        feature,
+        pubkey::Pubkey,
+        signature::{Keypair, Signature},
+        signer::Signer,
+        transaction::Transaction,
+    },
+    std::collections::BTreeMap,
+};
+
+/// How many slots either side of the epoch boundary to probe.
+const PROBE_MARGIN_SLOTS: u64 = 3;
+
+/// A probe that landed, and whether the stub program accepted it.
+struct Probe {
+    program_id: Pubkey,
+    slot: Slot,
+    result: Result<(), String>,
+}
+
+impl ValidatorContext {
+    /// Send a stub `write` instruction without preflight, so a rejected probe
+    /// still lands.
+    async fn send_probe(&self, program_id: &Pubkey) -> Signature {
+        let rpc_client = self.test_validator.get_async_rpc_client();
+        let target = Keypair::new();
+        let latest_blockhash = rpc_client.get_latest_blockhash().await.unwrap();
+        let transaction = Transaction::new_signed_with_payer(
+            &[cbmt_program_stub::write(
+                program_id,
+                &target.pubkey(),
+                &self.payer.pubkey(),
+                &target.pubkey().to_bytes(),
+            )],
+            Some(&self.payer.pubkey()),
+            &[&self.payer, &target],
+            latest_blockhash,
+        );
+        rpc_client
+            .send_transaction_with_config(
+                &transaction,
+                RpcSendTransactionConfig {
+                    skip_preflight: true,
+                    ..RpcSendTransactionConfig::default()
+                },
+            )
+            .await
+            .unwrap()
+    }
+
+    /// Wait out the current epoch, probing every program around the epoch
+    /// boundary, then check each probe was handled by the expected program.
+    pub async fn run_timing_matrix(&self, programs: &[Program]) {
+        // Feature Gate is a stateless builtin, with no program account before
+        // the migration, so its probes are rejected for that reason alone.
+        let (excluded, programs): (Vec<_>, Vec<_>) = programs
+            .iter()
+            .partition(|program| program.program_id() == feature::id());
+        for program in excluded {
+            println!(
+                "Not probing {}: it has no program account before the migration, so probes \
+                 are rejected whether or not it was migrated.",
+                program
+            );
+        }
+
+        let rpc_client = self.test_validator.get_async_rpc_client();
+        let epoch_info = rpc_client.get_epoch_info().await.unwrap();
+        let boundary_slot =
+            epoch_info.absolute_slot - epoch_info.slot_index + epoch_info.slots_in_epoch;
+
+        tokio::time::timeout(self.epoch_timeout, async {
+            while rpc_client.get_slot().await.unwrap() + PROBE_MARGIN_SLOTS < boundary_slot {
+                tokio::time::sleep(self.poll_interval).await;
+            }
+        })
+        .await
+        .expect("Timed out waiting for the epoch boundary");
+
+        let mut signatures = Vec::new();
+        tokio::time::timeout(self.epoch_timeout, async {
+            while rpc_client.get_slot().await.unwrap() <= boundary_slot + PROBE_MARGIN_SLOTS {
+                for program in &programs {
+                    let program_id = program.program_id();
+                    signatures.push((program_id, self.send_probe(&program_id).await));
+                }
+                tokio::time::sleep(self.poll_interval).await;
+            }
+        })
+        .await
+        .expect("Timed out probing the epoch boundary");
+
+        // Wait for the probes to land. Any dropped by the cluster are
+        // ignored.
+        self.wait_for_next_slot().await;
+        self.wait_for_next_slot().await;
+        let mut probes = Vec::new();
+        for chunk in signatures.chunks(256) {
+            let statuses = rpc_client
+                .get_signature_statuses(
+                    &chunk
+                        .iter()
+                        .map(|(_, signature)| *signature)
+                        .collect::<Vec<_>>(),
+                )
+                .await
+                .unwrap()
+                .value;
+            for ((program_id, _), status) in chunk.iter().zip(statuses) {
+                if let Some(status) = status {
+                    probes.push(Probe {
+                        program_id: *program_id,
+                        slot: status.slot,
+                        result: status.err.map_or(Ok(()), |err| Err(err.to_string())),
+                    });
+                }
+            }
+        }
+
+        let mut checks = Vec::new();
+        for program in programs {
+            let program_id = program.program_id();
+            let migration_slot = self
+                .get_account(&program.feature_gate())
+                .await
+                .and_then(|account| feature::from_account(&account))
+                .and_then(|feature| feature.activated_at)
+                .expect("Feature was not activated");
+
+            println!();
+            println!("    Program       : {}", program);
+            println!("    Migration Slot: {}", migration_slot);
+
+            let mut by_slot = BTreeMap::<Slot, Vec<&Probe>>::new();
+            for probe in probes.iter().filter(|probe| probe.program_id == program_id) {
+                by_slot.entry(probe.slot).or_default().push(probe);
+            }
+
+            // Each slot around the boundary and the migration must have been
+            // probed, or the matrix proves nothing about it.
+            for (label, slot) in [
+                ("Last slot of activation epoch", boundary_slot - 1),
+                ("First slot of new epoch", boundary_slot),
+                ("Migration slot", migration_slot),
+                ("Slot after migration", migration_slot + 1),
+            ] {
+                let hit = by_slot.contains_key(&slot);
+                println!(
+                    "      {:<32} {:>10}  {}",
+                    label,
+                    slot,
+                    if hit { "hit" } else { "not hit" }
+                );
+                checks.push((
+                    format!("{}: {} ({}) probed", program, label.to_lowercase(), slot),
+                    if hit {
+                        Ok(())
+                    } else {
+                        Err("no probe landed in this slot".to_string())
+                    },
+                ));
+            }
+
+            for (slot, slot_probes) in &by_slot {
+                // The migrated program is only visible from the slot after
+                // the migration.
+                let expect_bpf = *slot > migration_slot;
+                let label = format!(
+                    "{}: slot {} ({:+}) {}",
+                    program,
+                    slot,
+                    *slot as i64 - migration_slot as i64,
+                    if expect_bpf { "BPF" } else { "not BPF" }
+                );
+                let result = slot_probes
+                    .iter()
+                    .find_map(|probe| match (&probe.result, expect_bpf) {
+                        (Ok(()), false) => Some(Err("probe was accepted".to_string())),
+                        (Err(err), true) => Some(Err(format!("probe was rejected: {}", err))),
+                        _ => None,
+                    })
+                    .unwrap_or(Ok(()));
+                checks.push((label, result));
+            }
+        }
+
+        ensure!(
+            report_checks(&checks),
+            "Programs were not visible at the expected slots"
+        );
+    }
+}
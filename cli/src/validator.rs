@@ -1,15 +1,24 @@
 //! Test validator with context for testing.
 
 use {
-    crate::file::FileReader,
+    crate::{
+        file::FileReader,
+        report::{self, ensure, ensure_eq, fail},
+    },
+    base64::{prelude::BASE64_STANDARD, Engine},
+    clap::{Args, ValueEnum},
     indicatif::{ProgressBar, ProgressStyle},
     solana_rpc::rpc::JsonRpcConfig,
+    solana_rpc_client_api::config::RpcTransactionConfig,
     solana_sdk::{
         account::{Account, AccountSharedData, WritableAccount},
-        bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+        bpf_loader_upgradeable::{self, get_program_data_address, UpgradeableLoaderState},
+        clock::{Slot, DEFAULT_MS_PER_SLOT, DEFAULT_TICKS_PER_SLOT},
         commitment_config::CommitmentConfig,
-        epoch_schedule::EpochSchedule,
-        feature::Feature,
+        compute_budget::ComputeBudgetInstruction,
+        epoch_schedule::{EpochSchedule, MINIMUM_SLOTS_PER_EPOCH},
+        feature::{self, Feature},
+        fee_calculator::FeeRateGovernor,
         instruction::Instruction,
         pubkey::Pubkey,
         rent::Rent,
@@ -19,19 +28,104 @@ use {
         transaction::Transaction,
     },
     solana_test_validator::{TestValidator, TestValidatorGenesis, UpgradeableProgramInfo},
-    std::path::PathBuf,
+    solana_transaction_status::{UiTransactionEncoding, UiTransactionReturnData},
+    std::{collections::HashMap, future::Future, path::PathBuf, pin::Pin, time::Duration},
 };
 
+/// Compute unit limit for the stub's compute meter test.
+const STUB_COMPUTE_UNIT_LIMIT: u32 = 50_000;
+
 pub struct MigrationTarget<'a> {
     pub feature_id: Pubkey,
     pub buffer_address: Pubkey,
     pub elf_name: &'a str,
+    pub failure_mode: Option<FailureMode>,
+}
+
+/// A way of staging a malformed buffer account, which the runtime must
+/// refuse to migrate.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum FailureMode {
+    /// No buffer account is staged.
+    MissingBuffer,
+    /// The buffer has an authority set.
+    BufferAuthority,
+    /// The buffer holds only the first half of the ELF.
+    TruncatedElf,
+    /// The buffer's ELF header is overwritten.
+    CorruptElf,
+    /// The buffer is owned by the System program.
+    WrongOwner,
+    /// The buffer has no lamports.
+    ZeroLamports,
+}
+
+impl FailureMode {
+    /// Whether the buffer account exists on chain when staged this way.
+    pub fn buffer_exists(&self) -> bool {
+        !matches!(self, Self::MissingBuffer | Self::ZeroLamports)
+    }
+}
+
+impl std::fmt::Display for FailureMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = self.to_possible_value().expect("No skipped variants");
+        write!(f, "{}", value.get_name())
+    }
+}
+
+/// Settings for the test validator.
+#[derive(Args, Clone)]
+pub struct ValidatorArgs {
+    /// Slots per epoch (defaults to 50, at least 32).
+    #[arg(
+        short,
+        long,
+        default_value = "50",
+        value_parser = clap::value_parser!(u64).range(MINIMUM_SLOTS_PER_EPOCH..)
+    )]
+    pub slots_per_epoch: u64,
+    /// Ticks per slot (defaults to 64). Fewer ticks make for shorter slots,
+    /// and so faster epochs.
+    #[arg(long)]
+    pub ticks_per_slot: Option<u64>,
+    /// Maximum time to wait for the next slot or epoch, in seconds.
+    #[arg(long, default_value = "600")]
+    pub epoch_timeout: u64,
+}
+
+impl ValidatorArgs {
+    /// How often to poll the test validator while waiting, a quarter of a
+    /// slot.
+    fn poll_interval(&self) -> Duration {
+        let ticks_per_slot = self.ticks_per_slot.unwrap_or(DEFAULT_TICKS_PER_SLOT);
+        let slot_ms = DEFAULT_MS_PER_SLOT * ticks_per_slot / DEFAULT_TICKS_PER_SLOT;
+        Duration::from_millis((slot_ms / 4).clamp(10, 250))
+    }
+}
+
+/// A single step of the stub tests.
+type StubStep<'a> = Pin<Box<dyn Future<Output = ()> + 'a>>;
+
+/// What a confirmed transaction left behind, besides account changes.
+pub struct TransactionOutcome {
+    pub slot: Slot,
+    pub log_messages: Vec<String>,
+    pub return_data: Option<(Pubkey, Vec<u8>)>,
+    pub compute_units_consumed: Option<u64>,
 }
 
 pub struct ValidatorContext {
     pub test_validator: TestValidator,
     pub payer: Keypair,
-    pub slots_per_epoch: u64,
+    /// The ELF staged in each well-formed buffer account, by buffer address.
+    pub staged_elfs: HashMap<Pubkey, Vec<u8>>,
+    /// The authority of every upgrade buffer.
+    pub upgrade_authority: Keypair,
+    /// The buffer holding the same ELF with an authority, by buffer address.
+    pub upgrade_buffers: HashMap<Pubkey, Pubkey>,
+    pub epoch_timeout: Duration,
+    pub poll_interval: Duration,
 }
 
 impl ValidatorContext {
@@ -45,26 +139,189 @@ impl ValidatorContext {
 
     pub async fn assert_program_is_builtin(&self, program_id: &Pubkey) {
         let account = self.get_account(program_id).await.unwrap();
-        assert!(
+        ensure!(
             account.owner == solana_sdk::native_loader::id(),
             "Program is not a builtin"
         );
     }
 
-    pub async fn assert_program_is_bpf(&self, program_id: &Pubkey) {
-        let account = self.get_account(program_id).await.unwrap();
-        assert!(
-            account.owner == solana_sdk::bpf_loader_upgradeable::id(),
-            "Program is not BPF"
+    /// Verify the program was fully migrated from the buffer, reporting each
+    /// check individually.
+    pub async fn assert_program_is_bpf(
+        &self,
+        program_id: &Pubkey,
+        feature_id: &Pubkey,
+        buffer_address: &Pubkey,
+    ) {
+        let rpc_client = self.test_validator.get_async_rpc_client();
+        let programdata_address = get_program_data_address(program_id);
+        let mut checks: Vec<(&str, Result<(), String>)> = Vec::new();
+
+        let program_account = self.get_account(program_id).await;
+        checks.push((
+            "Program account is owned by the upgradeable loader",
+            match &program_account {
+                Some(account) if account.owner == bpf_loader_upgradeable::id() => Ok(()),
+                Some(account) => Err(format!("owned by {}", account.owner)),
+                None => Err("account not found".to_string()),
+            },
+        ));
+        checks.push((
+            "Program account points at the ProgramData PDA",
+            match program_account
+                .as_ref()
+                .map(|a| a.deserialize_data::<UpgradeableLoaderState>())
+            {
+                Some(Ok(UpgradeableLoaderState::Program {
+                    programdata_address: address,
+                })) if address == programdata_address => Ok(()),
+                Some(Ok(UpgradeableLoaderState::Program {
+                    programdata_address: address,
+                })) => Err(format!(
+                    "points at {}, expected {}",
+                    address, programdata_address
+                )),
+                _ => Err("not a program account".to_string()),
+            },
+        ));
+
+        let programdata_account = self.get_account(&programdata_address).await;
+        let programdata_state: Option<UpgradeableLoaderState> = programdata_account
+            .as_ref()
+            .and_then(|account| account.deserialize_data().ok());
+        checks.push((
+            "ProgramData has no upgrade authority",
+            match &programdata_state {
+                Some(UpgradeableLoaderState::ProgramData {
+                    upgrade_authority_address: None,
+                    ..
+                }) => Ok(()),
+                Some(UpgradeableLoaderState::ProgramData {
+                    upgrade_authority_address: Some(authority),
+                    ..
+                }) => Err(format!("authority is {}", authority)),
+                _ => Err("not a ProgramData account".to_string()),
+            },
+        ));
+
+        let activation_slot = self
+            .get_account(feature_id)
+            .await
+            .and_then(|account| feature::from_account(&account))
+            .and_then(|feature| feature.activated_at);
+        checks.push((
+            "ProgramData slot is the migration slot",
+            match (&programdata_state, activation_slot) {
+                (Some(UpgradeableLoaderState::ProgramData { slot, .. }), Some(activated_at))
+                    if *slot == activated_at =>
+                {
+                    Ok(())
+                }
+                (Some(UpgradeableLoaderState::ProgramData { slot, .. }), Some(activated_at)) => {
+                    Err(format!("slot is {}, expected {}", slot, activated_at))
+                }
+                (_, None) => Err("feature is not activated".to_string()),
+                _ => Err("not a ProgramData account".to_string()),
+            },
+        ));
+
+        let metadata_len = UpgradeableLoaderState::size_of_programdata_metadata();
+        checks.push((
+            "ProgramData ELF matches the buffer ELF",
+            match (&programdata_account, self.staged_elfs.get(buffer_address)) {
+                (Some(account), Some(elf))
+                    if account.data.get(metadata_len..) == Some(elf.as_slice()) =>
+                {
+                    Ok(())
+                }
+                (Some(_), Some(_)) => Err("ELF differs".to_string()),
+                (None, _) => Err("account not found".to_string()),
+                (_, None) => Err("no ELF was staged in the buffer".to_string()),
+            },
+        ));
+
+        for (label, account) in [
+            ("Program account is rent-exempt", &program_account),
+            ("ProgramData account is rent-exempt", &programdata_account),
+        ] {
+            let result = match account {
+                Some(account) => {
+                    let minimum_balance = rpc_client
+                        .get_minimum_balance_for_rent_exemption(account.data.len())
+                        .await
+                        .unwrap();
+                    if account.lamports >= minimum_balance {
+                        Ok(())
+                    } else {
+                        Err(format!(
+                            "{} lamports, need {}",
+                            account.lamports, minimum_balance
+                        ))
+                    }
+                }
+                None => Err("account not found".to_string()),
+            };
+            checks.push((label, result));
+        }
+
+        checks.push((
+            "Buffer account was cleared",
+            match self.get_account(buffer_address).await {
+                None => Ok(()),
+                Some(account) => Err(format!("{} lamports remain", account.lamports)),
+            },
+        ));
+
+        ensure!(
+            report_checks(&checks),
+            "Program {} was not fully migrated",
+            program_id
         );
     }
 
+    /// Ensure slots keep advancing and transactions keep landing.
+    pub async fn assert_validator_is_producing_blocks(&self) {
+        let rpc_client = self.test_validator.get_async_rpc_client();
+        let start_slot = rpc_client.get_slot().await.unwrap();
+        for _ in 0..3 {
+            self.wait_for_next_slot().await;
+        }
+        let slot = rpc_client.get_slot().await.unwrap();
+        ensure!(slot > start_slot, "Validator stopped producing blocks");
+
+        let recipient = Pubkey::new_unique();
+        self.send_transaction(
+            &[system_instruction::transfer(
+                &self.payer.pubkey(),
+                &recipient,
+                1_000_000,
+            )],
+            &self.payer.pubkey(),
+            &[&self.payer],
+        )
+        .await;
+        ensure!(self.get_account(&recipient).await.is_some());
+    }
+
     pub async fn send_transaction(
         &self,
         instructions: &[Instruction],
         payer: &Pubkey,
         signers: &[&Keypair],
     ) -> Signature {
+        self.try_send_transaction(instructions, payer, signers)
+            .await
+            .unwrap_or_else(|err| fail!("Transaction failed: {}", err))
+    }
+
+    /// Send a transaction, returning the error if it fails rather than
+    /// panicking.
+    pub async fn try_send_transaction(
+        &self,
+        instructions: &[Instruction],
+        payer: &Pubkey,
+        signers: &[&Keypair],
+    ) -> Result<Signature, String> {
         let rpc_client = self.test_validator.get_async_rpc_client();
         let latest_blockhash = rpc_client.get_latest_blockhash().await.unwrap();
         let transaction = Transaction::new_signed_with_payer(
@@ -79,7 +336,44 @@ impl ValidatorContext {
                 CommitmentConfig::confirmed(),
             )
             .await
-            .unwrap()
+            .map_err(|err| err.to_string())
+    }
+
+    /// Fetch a confirmed transaction's logs, return data and compute units.
+    pub async fn get_transaction_outcome(&self, signature: &Signature) -> TransactionOutcome {
+        let transaction = self
+            .test_validator
+            .get_async_rpc_client()
+            .get_transaction_with_config(
+                signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .await
+            .unwrap();
+        let meta = transaction
+            .transaction
+            .meta
+            .expect("Transaction has no status meta");
+
+        let return_data: Option<UiTransactionReturnData> = meta.return_data.into();
+        let return_data = return_data.map(|return_data| {
+            let (data, _) = return_data.data;
+            (
+                return_data.program_id.parse().unwrap(),
+                BASE64_STANDARD.decode(data).unwrap(),
+            )
+        });
+
+        TransactionOutcome {
+            slot: transaction.slot,
+            log_messages: Option::from(meta.log_messages).unwrap_or_default(),
+            return_data,
+            compute_units_consumed: meta.compute_units_consumed.into(),
+        }
     }
 
     pub async fn activate_feature(&self, feature_id: &Pubkey) {
@@ -91,6 +385,57 @@ impl ValidatorContext {
         .await;
     }
 
+    /// Activate a feature by writing an explicit pending `Feature` payload
+    /// into its account, then ensure the payload was stored.
+    pub async fn activate_feature_with_payload(&self, feature_id: &Pubkey) {
+        self.send_transaction(
+            &[cbmt_program_activator::activate_feature_with_payload(
+                feature_id, None,
+            )],
+            &self.payer.pubkey(),
+            &[&self.payer],
+        )
+        .await;
+
+        let account = self
+            .get_account(feature_id)
+            .await
+            .expect("Feature account not found");
+        ensure_eq!(
+            account.owner,
+            feature::id(),
+            "Feature account was not assigned to the Feature Gate program"
+        );
+        ensure_eq!(
+            feature::from_account(&account),
+            Some(Feature { activated_at: None }),
+            "Feature account does not hold a pending activation"
+        );
+    }
+
+    /// Activate several features with a single instruction.
+    pub async fn activate_features(&self, feature_ids: &[Pubkey]) {
+        self.send_transaction(
+            &[cbmt_program_activator::activate_features(feature_ids)],
+            &self.payer.pubkey(),
+            &[&self.payer],
+        )
+        .await;
+    }
+
+    /// Revert a pending activation through the Feature Gate program
+    /// stand-in.
+    pub async fn revoke_pending_activation(&self, feature_id: &Pubkey) {
+        self.send_transaction(
+            &[cbmt_program_activator::revoke_pending_activation(
+                feature_id,
+            )],
+            &self.payer.pubkey(),
+            &[&self.payer],
+        )
+        .await;
+    }
+
     pub async fn run_stub_test_write(&self, program_id: &Pubkey) {
         let target = Keypair::new();
         let write_data = Pubkey::new_unique().to_bytes();
@@ -108,8 +453,8 @@ impl ValidatorContext {
 
         let target_account = self.get_account(&target.pubkey()).await.unwrap();
 
-        assert_eq!(target_account.owner, *program_id);
-        assert_eq!(target_account.data, write_data);
+        ensure_eq!(target_account.owner, *program_id);
+        ensure_eq!(target_account.data, write_data);
     }
 
     pub async fn run_stub_test_burn(&self, program_id: &Pubkey) {
@@ -124,49 +469,269 @@ impl ValidatorContext {
         )
         .await;
 
-        assert!(self.get_account(&target.pubkey()).await.is_none());
+        ensure!(self.get_account(&target.pubkey()).await.is_none());
     }
 
-    pub async fn run_stub_tests(&self, program_id: &Pubkey) {
-        self.run_stub_test_write(program_id).await;
-        self.run_stub_test_burn(program_id).await;
+    pub async fn run_stub_test_return_data(&self, program_id: &Pubkey) {
+        let data = Pubkey::new_unique().to_bytes();
+        let signature = self
+            .send_transaction(
+                &[cbmt_program_stub::return_data(program_id, &data)],
+                &self.payer.pubkey(),
+                &[&self.payer],
+            )
+            .await;
+
+        let outcome = self.get_transaction_outcome(&signature).await;
+
+        ensure_eq!(outcome.return_data, Some((*program_id, data.to_vec())));
+    }
+
+    pub async fn run_stub_test_log(&self, program_id: &Pubkey) {
+        let data = Pubkey::new_unique().to_bytes();
+        let signature = self
+            .send_transaction(
+                &[cbmt_program_stub::log(program_id, &data)],
+                &self.payer.pubkey(),
+                &[&self.payer],
+            )
+            .await;
+
+        let outcome = self.get_transaction_outcome(&signature).await;
+
+        for expected in [
+            format!("Program log: {}", cbmt_program_stub::LOG_MESSAGE),
+            format!("Program data: {}", BASE64_STANDARD.encode(data)),
+        ] {
+            ensure!(
+                outcome.log_messages.contains(&expected),
+                "Missing log: {}",
+                expected
+            );
+        }
+    }
+
+    pub async fn run_stub_test_sysvars(&self, program_id: &Pubkey) {
+        let signature = self
+            .send_transaction(
+                &[cbmt_program_stub::sysvars(program_id)],
+                &self.payer.pubkey(),
+                &[&self.payer],
+            )
+            .await;
+
+        let outcome = self.get_transaction_outcome(&signature).await;
+        let (_, data) = outcome.return_data.expect("No return data");
+        let values = data
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect::<Vec<_>>();
+
+        let epoch_schedule = self
+            .test_validator
+            .get_async_rpc_client()
+            .get_epoch_schedule()
+            .await
+            .unwrap();
+
+        ensure_eq!(
+            values,
+            [
+                outcome.slot,
+                epoch_schedule.get_epoch(outcome.slot),
+                Rent::default().lamports_per_byte_year,
+                epoch_schedule.slots_per_epoch,
+            ]
+        );
+    }
+
+    pub async fn run_stub_test_realloc(&self, program_id: &Pubkey) {
+        let target = Keypair::new();
+        let write_data = Pubkey::new_unique().to_bytes();
+        let new_len = write_data.len() * 2;
+        self.send_transaction(
+            &[
+                cbmt_program_stub::write(
+                    program_id,
+                    &target.pubkey(),
+                    &self.payer.pubkey(),
+                    &write_data,
+                ),
+                cbmt_program_stub::realloc(
+                    program_id,
+                    &target.pubkey(),
+                    &self.payer.pubkey(),
+                    new_len as u64,
+                ),
+            ],
+            &self.payer.pubkey(),
+            &[&self.payer, &target],
+        )
+        .await;
+
+        let target_account = self.get_account(&target.pubkey()).await.unwrap();
+
+        ensure_eq!(target_account.owner, *program_id);
+        ensure_eq!(target_account.data.len(), new_len);
+        ensure_eq!(target_account.data[..write_data.len()], write_data);
+        ensure!(target_account.data[write_data.len()..]
+            .iter()
+            .all(|byte| *byte == 0));
+        ensure_eq!(
+            target_account.lamports,
+            Rent::default().minimum_balance(new_len)
+        );
+    }
+
+    pub async fn run_stub_test_nested_cpi(&self, program_id: &Pubkey) {
+        let target = Keypair::new();
+        let write_data = Pubkey::new_unique().to_bytes();
+        self.send_transaction(
+            &[cbmt_program_stub::nested_write(
+                program_id,
+                &target.pubkey(),
+                &self.payer.pubkey(),
+                &write_data,
+            )],
+            &self.payer.pubkey(),
+            &[&self.payer, &target],
+        )
+        .await;
+
+        let target_account = self.get_account(&target.pubkey()).await.unwrap();
+
+        ensure_eq!(target_account.owner, *program_id);
+        ensure_eq!(target_account.data, write_data);
+    }
+
+    pub async fn run_stub_test_pda(&self, program_id: &Pubkey) {
+        let seed = Pubkey::new_unique();
+        let write_data = Pubkey::new_unique().to_bytes();
+        self.send_transaction(
+            &[cbmt_program_stub::write_pda(
+                program_id,
+                &self.payer.pubkey(),
+                &seed,
+                &write_data,
+            )],
+            &self.payer.pubkey(),
+            &[&self.payer],
+        )
+        .await;
+
+        let (pda, _) = cbmt_program_stub::find_pda(program_id, &seed);
+        let pda_account = self.get_account(&pda).await.unwrap();
+
+        ensure_eq!(pda_account.owner, *program_id);
+        ensure_eq!(pda_account.data, write_data);
+    }
+
+    pub async fn run_stub_test_compute_units(&self, program_id: &Pubkey) {
+        let instructions = |limit| {
+            [
+                ComputeBudgetInstruction::set_compute_unit_limit(limit),
+                cbmt_program_stub::remaining_compute_units(program_id),
+            ]
+        };
+
+        let signature = self
+            .send_transaction(
+                &instructions(STUB_COMPUTE_UNIT_LIMIT),
+                &self.payer.pubkey(),
+                &[&self.payer],
+            )
+            .await;
+
+        let outcome = self.get_transaction_outcome(&signature).await;
+        let (_, data) = outcome.return_data.expect("No return data");
+        let remaining = u64::from_le_bytes(data.try_into().unwrap());
+        let consumed = outcome
+            .compute_units_consumed
+            .expect("No compute units consumed");
+
+        // The program keeps consuming units after reading the meter.
+        ensure!(remaining > 0 && remaining < STUB_COMPUTE_UNIT_LIMIT as u64);
+        ensure!(consumed + remaining >= STUB_COMPUTE_UNIT_LIMIT as u64);
+
+        // One unit short of what the program needed must exhaust the meter.
+        let result = self
+            .try_send_transaction(
+                &instructions(consumed as u32 - 1),
+                &self.payer.pubkey(),
+                &[&self.payer],
+            )
+            .await;
+        ensure!(result.is_err(), "Compute unit limit was not enforced");
+    }
+
+    /// Run every stub step, returning the result of each, so one failing
+    /// step doesn't hide the others.
+    pub async fn run_stub_tests(
+        &self,
+        program_id: &Pubkey,
+    ) -> Vec<(&'static str, Result<(), String>)> {
+        let steps: [(&'static str, StubStep<'_>); 9] = [
+            ("write", Box::pin(self.run_stub_test_write(program_id))),
+            ("burn", Box::pin(self.run_stub_test_burn(program_id))),
+            (
+                "return data",
+                Box::pin(self.run_stub_test_return_data(program_id)),
+            ),
+            ("log", Box::pin(self.run_stub_test_log(program_id))),
+            ("sysvars", Box::pin(self.run_stub_test_sysvars(program_id))),
+            ("realloc", Box::pin(self.run_stub_test_realloc(program_id))),
+            (
+                "nested CPI",
+                Box::pin(self.run_stub_test_nested_cpi(program_id)),
+            ),
+            ("PDA", Box::pin(self.run_stub_test_pda(program_id))),
+            (
+                "compute units",
+                Box::pin(self.run_stub_test_compute_units(program_id)),
+            ),
+        ];
+
+        let mut results = Vec::new();
+        for (step, future) in steps {
+            results.push((step, report::catch_assertion(future).await));
+        }
+        results
     }
 
     pub async fn wait_for_next_slot(&self) {
         let rpc_client = self.test_validator.get_async_rpc_client();
         let start_slot = rpc_client.get_slot().await.unwrap();
-        let mut slot = start_slot;
 
-        while slot == start_slot {
-            slot = rpc_client.get_slot().await.unwrap();
-            std::thread::sleep(std::time::Duration::from_millis(250));
-        }
+        tokio::time::timeout(self.epoch_timeout, async {
+            while rpc_client.get_slot().await.unwrap() == start_slot {
+                tokio::time::sleep(self.poll_interval).await;
+            }
+        })
+        .await
+        .expect("Timed out waiting for the next slot");
     }
 
     pub async fn wait_for_next_epoch(&self) {
         println!();
         let progress_bar = progress_bar("Waiting for next epoch...");
         let rpc_client = self.test_validator.get_async_rpc_client();
-
-        let get_slots_remaining = |this_slot: u64| {
-            let slots_remaining = self.slots_per_epoch - (this_slot % self.slots_per_epoch);
-            let progress_bar_incr =
-                (self.slots_per_epoch - slots_remaining) * 100 / self.slots_per_epoch;
-            progress_bar.set_message(format!("Slots remaining: {}", slots_remaining));
-            progress_bar.set_position(progress_bar_incr);
-            slots_remaining
-        };
-
-        loop {
-            let this_slot = rpc_client.get_slot().await.unwrap();
-            std::thread::sleep(std::time::Duration::from_millis(250));
-            if get_slots_remaining(this_slot) == 1 {
-                std::thread::sleep(std::time::Duration::from_millis(500));
-                break;
+        let start_epoch = rpc_client.get_epoch_info().await.unwrap().epoch;
+
+        let epoch = tokio::time::timeout(self.epoch_timeout, async {
+            loop {
+                let epoch_info = rpc_client.get_epoch_info().await.unwrap();
+                if epoch_info.epoch > start_epoch {
+                    break epoch_info.epoch;
+                }
+                let slots_remaining = epoch_info.slots_in_epoch - epoch_info.slot_index;
+                progress_bar.set_message(format!("Slots remaining: {}", slots_remaining));
+                progress_bar.set_position(epoch_info.slot_index * 100 / epoch_info.slots_in_epoch);
+                tokio::time::sleep(self.poll_interval).await;
             }
-        }
+        })
+        .await
+        .expect("Timed out waiting for the next epoch");
 
-        let epoch = rpc_client.get_epoch_info().await.unwrap().epoch;
         progress_bar.finish_with_message(format!("Epoch: {}", epoch));
         println!();
     }
@@ -174,8 +739,11 @@ impl ValidatorContext {
     pub async fn start(
         migration_targets: &[MigrationTarget<'_>],
         elf_directory: &str,
-        slots_per_epoch: u64,
+        validator_args: &ValidatorArgs,
+        feature_gate_stand_in: bool,
+        accounting: bool,
     ) -> Self {
+        let slots_per_epoch = validator_args.slots_per_epoch;
         solana_logger::setup();
 
         let file_reader = FileReader::new(&[elf_directory]);
@@ -187,25 +755,86 @@ impl ValidatorContext {
             .map(|mt| mt.feature_id)
             .collect::<Vec<_>>();
 
-        let accounts = migration_targets.iter().flat_map(|mt| {
-            [
-                (mt.feature_id, staged_feature_account()),
-                (mt.buffer_address, buffer_account(&file_reader, mt.elf_name)),
-            ]
-        });
+        let staged_elfs = migration_targets
+            .iter()
+            .filter(|mt| mt.failure_mode.is_none())
+            .map(|mt| (mt.buffer_address, file_reader.load_program_elf(mt.elf_name)))
+            .collect::<HashMap<_, _>>();
+
+        // A second buffer of the same ELF, with an authority, is staged for
+        // each well-formed buffer to attempt upgrades with after migration.
+        let upgrade_authority = Keypair::new();
+        let upgrade_buffers = staged_elfs
+            .keys()
+            .map(|buffer_address| (*buffer_address, Pubkey::new_unique()))
+            .collect::<HashMap<_, _>>();
+
+        let accounts = migration_targets
+            .iter()
+            .flat_map(|mt| {
+                let buffer = match mt.failure_mode {
+                    Some(FailureMode::MissingBuffer) => None,
+                    failure_mode => Some((
+                        mt.buffer_address,
+                        buffer_account(
+                            file_reader.load_program_elf(mt.elf_name),
+                            None,
+                            failure_mode,
+                        ),
+                    )),
+                };
+                [Some((mt.feature_id, staged_feature_account())), buffer]
+                    .into_iter()
+                    .flatten()
+            })
+            .chain(
+                upgrade_buffers
+                    .iter()
+                    .map(|(buffer_address, upgrade_buffer)| {
+                        (
+                            *upgrade_buffer,
+                            buffer_account(
+                                staged_elfs[buffer_address].clone(),
+                                Some(upgrade_authority.pubkey()),
+                                None,
+                            ),
+                        )
+                    }),
+            )
+            .collect::<Vec<_>>();
 
-        let bpf_programs = &[UpgradeableProgramInfo {
+        let mut bpf_programs = vec![UpgradeableProgramInfo {
             program_id: cbmt_program_activator::id(),
             loader: bpf_loader_upgradeable::id(),
             program_path: elf_path(elf_directory, "cbmt_program_activator"),
             upgrade_authority: Pubkey::new_unique(),
         }];
 
-        let (test_validator, payer) = TestValidatorGenesis::default()
+        // The activator stands in for the Feature Gate program, so it can
+        // revoke pending activations.
+        if feature_gate_stand_in {
+            bpf_programs.push(UpgradeableProgramInfo {
+                program_id: feature::id(),
+                loader: bpf_loader_upgradeable::id(),
+                program_path: elf_path(elf_directory, "cbmt_program_activator"),
+                upgrade_authority: Pubkey::new_unique(),
+            });
+        }
+
+        let mut genesis = TestValidatorGenesis::default();
+        if let Some(ticks_per_slot) = validator_args.ticks_per_slot {
+            genesis.ticks_per_slot(ticks_per_slot);
+        }
+        // When checking accounting, fees are disabled, so transactions don't
+        // burn lamports and skew the total supply.
+        if accounting {
+            genesis.fee_rate_governor(FeeRateGovernor::new(0, 0));
+        }
+        let (test_validator, payer) = genesis
             .epoch_schedule(epoch_schedule)
             .deactivate_features(&deactivate_list)
             .add_accounts(accounts)
-            .add_upgradeable_programs_with_path(bpf_programs)
+            .add_upgradeable_programs_with_path(&bpf_programs)
             .rpc_config(JsonRpcConfig {
                 enable_rpc_transaction_history: true,
                 ..JsonRpcConfig::default_for_test()
@@ -216,9 +845,32 @@ impl ValidatorContext {
         Self {
             test_validator,
             payer,
-            slots_per_epoch,
+            staged_elfs,
+            upgrade_authority,
+            upgrade_buffers,
+            epoch_timeout: Duration::from_secs(validator_args.epoch_timeout),
+            poll_interval: validator_args.poll_interval(),
+        }
+    }
+}
+
+/// Print and record the result of each check. Returns whether every check
+/// passed.
+pub fn report_checks<L: std::fmt::Display>(checks: &[(L, Result<(), String>)]) -> bool {
+    println!();
+    let mut passed = true;
+    for (label, result) in checks {
+        report::record(label, result);
+        match result {
+            Ok(()) => println!("      {:<56} ok", label),
+            Err(err) => {
+                passed = false;
+                println!("      {:<56} FAILED ({})", label, err);
+            }
         }
     }
+    println!();
+    passed
 }
 
 // Create a "staged" feature account, owned by the activator program.
@@ -228,19 +880,36 @@ fn staged_feature_account() -> AccountSharedData {
     AccountSharedData::new(lamports, space, &cbmt_program_activator::id())
 }
 
-// Create a buffer account with the provided ELF.
-fn buffer_account(file_reader: &FileReader, elf_name: &str) -> AccountSharedData {
-    let elf = file_reader.load_program_elf(elf_name);
+// Create a buffer account with the provided ELF and authority, malformed
+// according to the failure mode, if any.
+fn buffer_account(
+    mut elf: Vec<u8>,
+    mut authority_address: Option<Pubkey>,
+    failure_mode: Option<FailureMode>,
+) -> AccountSharedData {
+    let mut owner = bpf_loader_upgradeable::id();
+
+    match failure_mode {
+        Some(FailureMode::BufferAuthority) => authority_address = Some(Pubkey::new_unique()),
+        Some(FailureMode::TruncatedElf) => elf.truncate(elf.len() / 2),
+        Some(FailureMode::CorruptElf) => {
+            let header_len = elf.len().min(64);
+            elf[..header_len].fill(0xff);
+        }
+        Some(FailureMode::WrongOwner) => owner = solana_sdk::system_program::id(),
+        _ => (),
+    }
 
     let space = UpgradeableLoaderState::size_of_buffer(elf.len());
-    let lamports = Rent::default().minimum_balance(space);
+    let lamports = match failure_mode {
+        Some(FailureMode::ZeroLamports) => 0,
+        _ => Rent::default().minimum_balance(space),
+    };
     let mut account = AccountSharedData::new_data_with_space(
         lamports,
-        &UpgradeableLoaderState::Buffer {
-            authority_address: None,
-        },
+        &UpgradeableLoaderState::Buffer { authority_address },
         space,
-        &bpf_loader_upgradeable::id(),
+        &owner,
     )
     .unwrap();
     account.data_as_mut_slice()[UpgradeableLoaderState::size_of_buffer_metadata()..]
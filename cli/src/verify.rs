@@ -0,0 +1,183 @@
+//! Buffer account verification.
+
+use {
+    crate::{
+        output::{output, warning},
+        program::{ExpectedAuthority, Program},
+    },
+    sha2::{Digest, Sha256},
+    solana_sdk::{
+        account::Account,
+        bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    },
+};
+
+/// The size of a 64-bit ELF header.
+const EHDR_LEN: usize = 64;
+
+/// The type of a section occupying no space in the file.
+const SHT_NOBITS: u32 = 8;
+
+/// Verify a buffer account holds the expected ELF, returning the ELF without
+/// any padding after it.
+pub fn verify_buffer_account(account: &Account, program: &Program) -> Result<Vec<u8>, String> {
+    if account.owner != bpf_loader_upgradeable::id() {
+        return Err(format!(
+            "Buffer account is owned by {}, expected {}",
+            account.owner,
+            bpf_loader_upgradeable::id()
+        ));
+    }
+
+    let metadata_len = UpgradeableLoaderState::size_of_buffer_metadata();
+    if account.data.len() < metadata_len {
+        return Err("Buffer account is too small".to_string());
+    }
+
+    let authority_address = match account.deserialize_data() {
+        Ok(UpgradeableLoaderState::Buffer { authority_address }) => authority_address,
+        _ => return Err("Account is not an upgradeable loader buffer".to_string()),
+    };
+
+    let authority = match authority_address {
+        Some(address) => ExpectedAuthority::Some(address),
+        None => ExpectedAuthority::None,
+    };
+    output(&format!("Buffer authority: {}", authority));
+
+    match program.buffer_authority() {
+        Some(expected) if authority != expected => {
+            return Err(format!(
+                "Buffer authority mismatch: expected {}, found {}",
+                expected, authority
+            ));
+        }
+        Some(_) => (),
+        None => warn_unpinned(program, "buffer authority"),
+    }
+
+    verify_elf(&account.data[metadata_len..], program)
+}
+
+/// Verify an ELF matches the expected hash, returning the ELF without any
+/// padding after it.
+pub fn verify_elf(elf: &[u8], program: &Program) -> Result<Vec<u8>, String> {
+    let elf = trim_padding(elf);
+    let hash = sha256_hex(elf);
+    output(&format!("ELF hash (SHA-256): {}", hash));
+
+    match program.elf_hash() {
+        Some(expected) if hash != expected => {
+            return Err(format!(
+                "ELF hash mismatch: expected {}, found {}",
+                expected, hash
+            ));
+        }
+        Some(_) => (),
+        None => warn_unpinned(program, "ELF hash"),
+    }
+
+    Ok(elf.to_vec())
+}
+
+fn warn_unpinned(program: &Program, pin: &str) {
+    if !program.pins_waived() {
+        warning(&format!(
+            "Program `{}` has no pinned {}, so it was NOT verified. Pin it in a registry file.",
+            program.name(),
+            pin
+        ));
+    }
+}
+
+/// Strip the padding left over from writing an ELF into a larger buffer
+/// account.
+///
+/// The ELF ends with the last of its program headers, section headers and
+/// section contents, according to its header. A valid ELF can itself end with
+/// zeros, so trailing zeros alone can't be stripped. Data that isn't a 64-bit
+/// little-endian ELF is returned in full.
+pub fn trim_padding(elf: &[u8]) -> &[u8] {
+    let len = elf_len(elf).unwrap_or(elf.len()).min(elf.len());
+    &elf[..len]
+}
+
+fn elf_len(elf: &[u8]) -> Option<usize> {
+    if elf.get(..6)? != b"\x7fELF\x02\x01" {
+        return None;
+    }
+
+    let phoff = read_u64(elf, 0x20)?;
+    let shoff = read_u64(elf, 0x28)?;
+    let phentsize = read_u16(elf, 0x36)?;
+    let phnum = read_u16(elf, 0x38)?;
+    let shentsize = read_u16(elf, 0x3a)?;
+    let shnum = read_u16(elf, 0x3c)?;
+
+    let mut len = EHDR_LEN
+        .max(phoff.checked_add(phentsize.checked_mul(phnum)?)?)
+        .max(shoff.checked_add(shentsize.checked_mul(shnum)?)?);
+    for index in 0..shnum {
+        let header = shoff.checked_add(index.checked_mul(shentsize)?)?;
+        if read_u32(elf, header.checked_add(4)?)? == SHT_NOBITS {
+            continue;
+        }
+        let offset = read_u64(elf, header.checked_add(0x18)?)?;
+        let size = read_u64(elf, header.checked_add(0x20)?)?;
+        len = len.max(offset.checked_add(size)?);
+    }
+    Some(len)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<usize> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes(bytes.try_into().ok()?) as usize)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<usize> {
+    let bytes = data.get(offset..offset.checked_add(8)?)?;
+    usize::try_from(u64::from_le_bytes(bytes.try_into().ok()?)).ok()
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An ELF header followed by a single, all-zero, section header.
+    fn elf_ending_with_zeros() -> Vec<u8> {
+        let mut elf = vec![0; 128];
+        elf[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        elf[0x28..0x30].copy_from_slice(&64u64.to_le_bytes());
+        elf[0x3a..0x3c].copy_from_slice(&64u16.to_le_bytes());
+        elf[0x3c..0x3e].copy_from_slice(&1u16.to_le_bytes());
+        elf
+    }
+
+    #[test]
+    fn test_trim_padding_keeps_trailing_zeros_of_elf() {
+        let elf = elf_ending_with_zeros();
+        let mut padded = elf.clone();
+        padded.extend_from_slice(&[0; 32]);
+
+        assert_eq!(trim_padding(&elf), &elf[..]);
+        assert_eq!(trim_padding(&padded), &elf[..]);
+    }
+
+    #[test]
+    fn test_trim_padding_returns_non_elf_in_full() {
+        let data = [1, 2, 3, 0, 0];
+        assert_eq!(trim_padding(&data), &data[..]);
+    }
+}
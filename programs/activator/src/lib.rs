@@ -6,6 +6,11 @@
 //! The test harness will create a feature account owned by this program at
 //! genesis. Then, it can invoke this program to assign ownership to
 //! `Feature1111...`, activating the feature without the keypair.
+//!
+//! To revoke a pending activation, the harness can deploy this program at the
+//! `Feature1111...` address as well, as a stand-in for the Feature Gate
+//! program. Only the owner of a pending feature account can reassign it back
+//! to this program.
 use solana_program::{
     account_info::AccountInfo,
     entrypoint::ProgramResult,
@@ -26,9 +31,111 @@ pub fn activate_feature(feature_id: &Pubkey) -> Instruction {
     Instruction::new_with_bytes(crate::id(), &[], vec![AccountMeta::new(*feature_id, false)])
 }
 
-pub fn process(_program_id: &Pubkey, accounts: &[AccountInfo], _input: &[u8]) -> ProgramResult {
-    accounts
-        .first()
-        .ok_or(ProgramError::NotEnoughAccountKeys)
-        .map(|info| info.assign(&FEATURE_GATE_PROGRAM_ID))
+pub fn activate_features(feature_ids: &[Pubkey]) -> Instruction {
+    Instruction::new_with_bytes(
+        crate::id(),
+        &[0],
+        feature_ids
+            .iter()
+            .map(|feature_id| AccountMeta::new(*feature_id, false))
+            .collect(),
+    )
+}
+
+/// Write an explicit `Feature { activated_at }` payload into the feature
+/// account, then assign it to `Feature1111...`.
+pub fn activate_feature_with_payload(
+    feature_id: &Pubkey,
+    activated_at: Option<u64>,
+) -> Instruction {
+    let mut input = vec![1];
+    input.extend_from_slice(&feature_payload(activated_at));
+    Instruction::new_with_bytes(
+        crate::id(),
+        &input,
+        vec![AccountMeta::new(*feature_id, false)],
+    )
+}
+
+/// Revert a pending activation, reassigning the feature account back to
+/// this program. Only succeeds if this program is deployed at the
+/// `Feature1111...` address.
+pub fn revoke_pending_activation(feature_id: &Pubkey) -> Instruction {
+    Instruction::new_with_bytes(
+        FEATURE_GATE_PROGRAM_ID,
+        &[2],
+        vec![AccountMeta::new(*feature_id, false)],
+    )
+}
+
+/// Serialize a `Feature`, which is just an `Option<u64>`.
+fn feature_payload(activated_at: Option<u64>) -> [u8; 9] {
+    let mut payload = [0; 9];
+    if let Some(slot) = activated_at {
+        payload[0] = 1;
+        payload[1..].copy_from_slice(&slot.to_le_bytes());
+    }
+    payload
+}
+
+pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> ProgramResult {
+    match input.split_first() {
+        None => {
+            // Activate:
+            // * Assign the feature account to `Feature1111...`.
+            accounts
+                .first()
+                .ok_or(ProgramError::NotEnoughAccountKeys)
+                .map(|info| info.assign(&FEATURE_GATE_PROGRAM_ID))
+        }
+        Some((&0, _)) => {
+            // Activate many:
+            // * Assign every feature account to `Feature1111...`.
+            if accounts.is_empty() {
+                Err(ProgramError::NotEnoughAccountKeys)?
+            }
+            accounts
+                .iter()
+                .for_each(|info| info.assign(&FEATURE_GATE_PROGRAM_ID));
+            Ok(())
+        }
+        Some((&1, rest)) => {
+            // Activate with payload:
+            // * Write the `Feature` payload into the feature account.
+            // * Assign the feature account to `Feature1111...`.
+            let info = accounts.first().ok_or(ProgramError::NotEnoughAccountKeys)?;
+            let payload = rest.get(..9).ok_or(ProgramError::InvalidInstructionData)?;
+
+            let mut data = info.try_borrow_mut_data()?;
+            data.get_mut(..9)
+                .ok_or(ProgramError::AccountDataTooSmall)?
+                .copy_from_slice(payload);
+            drop(data);
+
+            info.assign(&FEATURE_GATE_PROGRAM_ID);
+            Ok(())
+        }
+        Some((&2, _)) => {
+            // Revoke:
+            // * Ensure the feature is still pending.
+            // * Clear the feature account's data.
+            // * Assign the feature account back to this program.
+            if *program_id != FEATURE_GATE_PROGRAM_ID {
+                Err(ProgramError::IncorrectProgramId)?
+            }
+            let info = accounts.first().ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+            let mut data = info.try_borrow_mut_data()?;
+            if data.first() != Some(&0) {
+                // The feature is already activated.
+                Err(ProgramError::InvalidAccountData)?
+            }
+            data.fill(0);
+            drop(data);
+
+            info.assign(&crate::id());
+            Ok(())
+        }
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
 }
@@ -5,12 +5,21 @@
 //!
 //! The program is designed to be deterministic, to allow for the same test
 //! suite to be used across different migrations.
+//!
+//! Besides writing and burning accounts, it exercises the parts of the
+//! runtime a migrated program relies on: return data, logs, sysvars,
+//! realloc, nested CPI, PDA signing and the compute meter.
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    compute_units::sol_remaining_compute_units,
     entrypoint::ProgramResult,
+    epoch_schedule::EpochSchedule,
     incinerator,
     instruction::{AccountMeta, Instruction},
-    program::invoke,
+    log::sol_log_data,
+    msg,
+    program::{invoke, invoke_signed, set_return_data},
     program_error::ProgramError,
     pubkey::Pubkey,
     rent::Rent,
@@ -18,6 +27,11 @@ use solana_program::{
     sysvar::Sysvar,
 };
 
+/// The message logged by the `log` instruction.
+pub const LOG_MESSAGE: &str = "Hello from the stub program";
+
+const PDA_SEED: &[u8] = b"stub";
+
 #[cfg(feature = "sbf-entrypoint")]
 solana_program::entrypoint!(process);
 
@@ -52,6 +66,89 @@ pub fn burn(program_id: &Pubkey, target_address: &Pubkey) -> Instruction {
     )
 }
 
+pub fn return_data(program_id: &Pubkey, data: &[u8]) -> Instruction {
+    let mut input = vec![2];
+    input.extend_from_slice(data);
+    Instruction::new_with_bytes(*program_id, &input, vec![])
+}
+
+pub fn log(program_id: &Pubkey, data: &[u8]) -> Instruction {
+    let mut input = vec![3];
+    input.extend_from_slice(data);
+    Instruction::new_with_bytes(*program_id, &input, vec![])
+}
+
+pub fn sysvars(program_id: &Pubkey) -> Instruction {
+    Instruction::new_with_bytes(*program_id, &[4], vec![])
+}
+
+pub fn realloc(
+    program_id: &Pubkey,
+    target_address: &Pubkey,
+    payer_address: &Pubkey,
+    new_len: u64,
+) -> Instruction {
+    let mut input = vec![5];
+    input.extend_from_slice(&new_len.to_le_bytes());
+    Instruction::new_with_bytes(
+        *program_id,
+        &input,
+        vec![
+            AccountMeta::new(*target_address, false),
+            AccountMeta::new(*payer_address, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn nested_write(
+    program_id: &Pubkey,
+    target_address: &Pubkey,
+    payer_address: &Pubkey,
+    data: &[u8],
+) -> Instruction {
+    let mut input = vec![6];
+    input.extend_from_slice(data);
+    Instruction::new_with_bytes(
+        *program_id,
+        &input,
+        vec![
+            AccountMeta::new(*target_address, true),
+            AccountMeta::new(*payer_address, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(*program_id, false),
+        ],
+    )
+}
+
+pub fn find_pda(program_id: &Pubkey, seed: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PDA_SEED, seed.as_ref()], program_id)
+}
+
+pub fn write_pda(
+    program_id: &Pubkey,
+    payer_address: &Pubkey,
+    seed: &Pubkey,
+    data: &[u8],
+) -> Instruction {
+    let mut input = vec![7];
+    input.extend_from_slice(seed.as_ref());
+    input.extend_from_slice(data);
+    Instruction::new_with_bytes(
+        *program_id,
+        &input,
+        vec![
+            AccountMeta::new(find_pda(program_id, seed).0, false),
+            AccountMeta::new(*payer_address, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn remaining_compute_units(program_id: &Pubkey) -> Instruction {
+    Instruction::new_with_bytes(*program_id, &[8], vec![])
+}
+
 pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> ProgramResult {
     match input.split_first() {
         Some((&0, rest)) => {
@@ -113,6 +210,148 @@ pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> P
 
             Ok(())
         }
+        Some((&2, rest)) => {
+            // Return data:
+            // * Set the input data as the return data.
+            set_return_data(rest);
+
+            Ok(())
+        }
+        Some((&3, rest)) => {
+            // Log:
+            // * Log a fixed message.
+            // * Log the input data.
+            msg!(LOG_MESSAGE);
+            sol_log_data(&[rest]);
+
+            Ok(())
+        }
+        Some((&4, _)) => {
+            // Sysvars:
+            // * Read the Clock, Rent and EpochSchedule sysvars.
+            // * Set the slot, epoch, lamports per byte-year and slots per
+            //   epoch as the return data.
+            let clock = <Clock as Sysvar>::get()?;
+            let rent = <Rent as Sysvar>::get()?;
+            let epoch_schedule = <EpochSchedule as Sysvar>::get()?;
+
+            let mut data = Vec::with_capacity(32);
+            data.extend_from_slice(&clock.slot.to_le_bytes());
+            data.extend_from_slice(&clock.epoch.to_le_bytes());
+            data.extend_from_slice(&rent.lamports_per_byte_year.to_le_bytes());
+            data.extend_from_slice(&epoch_schedule.slots_per_epoch.to_le_bytes());
+            set_return_data(&data);
+
+            Ok(())
+        }
+        Some((&5, rest)) => {
+            // Realloc:
+            // * Fund the target account to rent-exemption at the new length.
+            // * Resize the target account, zero-initializing any new space.
+            let accounts_iter = &mut accounts.iter();
+            let target_info = next_account_info(accounts_iter)?;
+            let payer_info = next_account_info(accounts_iter)?;
+            let _system_program_info = next_account_info(accounts_iter)?;
+
+            if !payer_info.is_signer {
+                Err(ProgramError::MissingRequiredSignature)?
+            }
+            if target_info.owner != program_id {
+                Err(ProgramError::IncorrectProgramId)?
+            }
+
+            let new_len = rest
+                .get(..8)
+                .and_then(|bytes| bytes.try_into().ok())
+                .map(u64::from_le_bytes)
+                .ok_or(ProgramError::InvalidInstructionData)? as usize;
+
+            let rent = <Rent as Sysvar>::get()?;
+            let lamports = rent
+                .minimum_balance(new_len)
+                .saturating_sub(target_info.lamports());
+            if lamports > 0 {
+                invoke(
+                    &system_instruction::transfer(payer_info.key, target_info.key, lamports),
+                    &[payer_info.clone(), target_info.clone()],
+                )?;
+            }
+
+            target_info.realloc(new_len, true)?;
+
+            Ok(())
+        }
+        Some((&6, rest)) => {
+            // Nested write:
+            // * Invoke this program's write instruction, which in turn
+            //   invokes the System program.
+            let accounts_iter = &mut accounts.iter();
+            let target_info = next_account_info(accounts_iter)?;
+            let payer_info = next_account_info(accounts_iter)?;
+            let system_program_info = next_account_info(accounts_iter)?;
+            let program_info = next_account_info(accounts_iter)?;
+
+            invoke(
+                &write(program_id, target_info.key, payer_info.key, rest),
+                &[
+                    target_info.clone(),
+                    payer_info.clone(),
+                    system_program_info.clone(),
+                    program_info.clone(),
+                ],
+            )?;
+
+            if target_info.owner != program_id {
+                Err(ProgramError::IncorrectProgramId)?
+            }
+
+            Ok(())
+        }
+        Some((&7, rest)) => {
+            // Write PDA:
+            // * Create the PDA for the seed, signing for it, owned by the
+            //   program.
+            // * Write the input data into it.
+            let accounts_iter = &mut accounts.iter();
+            let pda_info = next_account_info(accounts_iter)?;
+            let payer_info = next_account_info(accounts_iter)?;
+            let _system_program_info = next_account_info(accounts_iter)?;
+
+            if rest.len() < 32 {
+                Err(ProgramError::InvalidInstructionData)?
+            }
+            let (seed, data) = rest.split_at(32);
+            let seed = Pubkey::try_from(seed).map_err(|_| ProgramError::InvalidInstructionData)?;
+
+            let (pda, bump) = find_pda(program_id, &seed);
+            if *pda_info.key != pda {
+                Err(ProgramError::InvalidSeeds)?
+            }
+
+            let rent = <Rent as Sysvar>::get()?;
+            invoke_signed(
+                &system_instruction::create_account(
+                    payer_info.key,
+                    pda_info.key,
+                    rent.minimum_balance(data.len()),
+                    data.len() as u64,
+                    program_id,
+                ),
+                &[payer_info.clone(), pda_info.clone()],
+                &[&[PDA_SEED, seed.as_ref(), &[bump]]],
+            )?;
+
+            pda_info.try_borrow_mut_data()?.copy_from_slice(data);
+
+            Ok(())
+        }
+        Some((&8, _)) => {
+            // Remaining compute units:
+            // * Set the remaining compute units as the return data.
+            set_return_data(&sol_remaining_compute_units().to_le_bytes());
+
+            Ok(())
+        }
         _ => Err(ProgramError::InvalidInstructionData),
     }
 }